@@ -0,0 +1,120 @@
+use std::{alloc::Layout, ptr::NonNull};
+
+/// A source of memory for the buffer of an [`UntypedVec`](crate::UntypedVec)
+///
+/// This is a minimal stand-in for the unstable [`Allocator`](std::alloc::Allocator) trait, so
+/// vectors can live in arenas or be tracked on stable. Implementors of the unstable trait can be
+/// used through `AllocatorAdapter` with the `allocator_api` feature
+///
+/// # Safety
+/// A pointer returned by `allocate` or `reallocate` must point to a block of memory that fits the
+/// requested layout and stays valid until it is passed to `deallocate` or `reallocate`
+pub unsafe trait RawAllocator {
+    /// Allocates a block of memory fitting `layout`, returning `None` on failure.
+    /// The size of `layout` is never zero
+    fn allocate(&self, layout: Layout) -> Option<NonNull<u8>>;
+
+    /// Frees a block of memory
+    ///
+    /// # Safety
+    /// `ptr` must have been allocated by this allocator with `layout`
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout);
+
+    /// Moves a block of memory to one of `new_size` bytes with the same alignment, preserving its
+    /// contents up to the smaller of both sizes. On failure the old block is left untouched
+    ///
+    /// The default implementation allocates a new block and copies the contents over
+    ///
+    /// # Safety
+    /// `ptr` must have been allocated by this allocator with `old_layout`, and `new_size` must be
+    /// non-zero and form a valid layout with the alignment of `old_layout`
+    unsafe fn reallocate(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_size: usize,
+    ) -> Option<NonNull<u8>> {
+        let new_layout = Layout::from_size_align_unchecked(new_size, old_layout.align());
+        let new_ptr = self.allocate(new_layout)?;
+        std::ptr::copy_nonoverlapping(
+            ptr.as_ptr(),
+            new_ptr.as_ptr(),
+            old_layout.size().min(new_size),
+        );
+        self.deallocate(ptr, old_layout);
+        Some(new_ptr)
+    }
+}
+
+/// The global allocator, as used by [`std::alloc::alloc`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Global;
+
+unsafe impl RawAllocator for Global {
+    fn allocate(&self, layout: Layout) -> Option<NonNull<u8>> {
+        NonNull::new(unsafe { std::alloc::alloc(layout) })
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        std::alloc::dealloc(ptr.as_ptr(), layout)
+    }
+
+    unsafe fn reallocate(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_size: usize,
+    ) -> Option<NonNull<u8>> {
+        NonNull::new(std::alloc::realloc(ptr.as_ptr(), old_layout, new_size))
+    }
+}
+
+unsafe impl<A: RawAllocator + ?Sized> RawAllocator for &A {
+    fn allocate(&self, layout: Layout) -> Option<NonNull<u8>> {
+        (**self).allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        (**self).deallocate(ptr, layout)
+    }
+
+    unsafe fn reallocate(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_size: usize,
+    ) -> Option<NonNull<u8>> {
+        (**self).reallocate(ptr, old_layout, new_size)
+    }
+}
+
+/// Uses an implementor of the unstable [`Allocator`](std::alloc::Allocator) trait as a [`RawAllocator`]
+#[cfg(feature = "allocator_api")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AllocatorAdapter<A>(pub A);
+
+#[cfg(feature = "allocator_api")]
+unsafe impl<A: std::alloc::Allocator> RawAllocator for AllocatorAdapter<A> {
+    fn allocate(&self, layout: Layout) -> Option<NonNull<u8>> {
+        self.0.allocate(layout).ok().map(NonNull::cast)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.0.deallocate(ptr, layout)
+    }
+
+    unsafe fn reallocate(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_size: usize,
+    ) -> Option<NonNull<u8>> {
+        let new_layout = Layout::from_size_align_unchecked(new_size, old_layout.align());
+        let result = if new_size >= old_layout.size() {
+            self.0.grow(ptr, old_layout, new_layout)
+        } else {
+            self.0.shrink(ptr, old_layout, new_layout)
+        };
+        result.ok().map(NonNull::cast)
+    }
+}
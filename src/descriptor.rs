@@ -0,0 +1,317 @@
+use std::{
+    alloc::Layout,
+    any::{type_name, TypeId},
+    cmp::Ordering,
+    fmt,
+    hash::Hasher,
+};
+
+use crate::{utils, UntypedVecError};
+
+/// Describes the element type of an [`UntypedVec`](crate::UntypedVec) at runtime
+///
+/// Usually created with [`ElementDescriptor::of`], but can also be assembled by hand
+/// for types that are only known at runtime
+#[derive(Debug, Clone, Copy)]
+pub struct ElementDescriptor {
+    pub(crate) layout: Layout,
+    pub(crate) drop: Option<unsafe fn(*mut u8)>,
+    pub(crate) type_id: Option<TypeId>,
+    pub(crate) type_name: &'static str,
+    pub(crate) clone: Option<unsafe fn(*const u8, *mut u8)>,
+    pub(crate) copy: bool,
+    pub(crate) pod: bool,
+    pub(crate) debug: Option<DebugFn>,
+    pub(crate) eq: Option<EqFn>,
+    pub(crate) hash: Option<HashFn>,
+    pub(crate) cmp: Option<CmpFn>,
+}
+
+/// Formats the element behind the pointer, like [`fmt::Debug::fmt`]
+pub type DebugFn = unsafe fn(*const u8, &mut fmt::Formatter<'_>) -> fmt::Result;
+/// Compares the elements behind the pointers, like [`PartialEq::eq`]
+pub type EqFn = unsafe fn(*const u8, *const u8) -> bool;
+/// Feeds the element behind the pointer into the hasher, like [`Hash::hash`](std::hash::Hash::hash)
+pub type HashFn = unsafe fn(*const u8, &mut dyn Hasher);
+/// Orders the elements behind the pointers, like [`Ord::cmp`]
+pub type CmpFn = unsafe fn(*const u8, *const u8) -> Ordering;
+
+impl ElementDescriptor {
+    /// Describes the Rust type `T`
+    pub fn of<T: 'static>() -> Self {
+        Self {
+            layout: Layout::new::<T>(),
+            drop: std::mem::needs_drop::<T>().then_some(utils::drop_ptr::<T> as unsafe fn(*mut u8)),
+            type_id: Some(TypeId::of::<T>()),
+            type_name: type_name::<T>(),
+            clone: None,
+            copy: false,
+            pod: false,
+            debug: None,
+            eq: None,
+            hash: None,
+            cmp: None,
+        }
+    }
+
+    /// Describes a type by its layout and destructor alone.
+    /// Vectors of such a type can only be accessed through the raw API
+    ///
+    /// # Safety
+    /// The size of `layout` must be a multiple of its alignment. If given, `drop` must be sound to
+    /// call exactly once on a pointer to any value stored in the vector. `type_name` must uniquely
+    /// identify the type, as descriptors without a [`TypeId`] are told apart by name and layout,
+    /// see [`ElementDescriptor::describes_same_type`]
+    pub unsafe fn new(
+        type_name: &'static str,
+        layout: Layout,
+        drop: Option<unsafe fn(*mut u8)>,
+    ) -> Self {
+        debug_assert_eq!(layout.size() % layout.align(), 0);
+
+        Self {
+            layout,
+            drop,
+            type_id: None,
+            type_name,
+            clone: None,
+            copy: false,
+            pod: false,
+            debug: None,
+            eq: None,
+            hash: None,
+            cmp: None,
+        }
+    }
+
+    /// Associates the described type with a Rust type, enabling typed access
+    ///
+    /// # Safety
+    /// `type_id` must belong to a type whose layout and destructor match this descriptor
+    pub unsafe fn with_type_id(mut self, type_id: TypeId) -> Self {
+        self.type_id = Some(type_id);
+        self
+    }
+
+    /// Captures `T`'s [`Clone`] implementation, allowing vectors to be cloned without knowing `T`
+    #[track_caller]
+    pub fn with_clone<T: Clone + 'static>(mut self) -> Self {
+        self.assert_is::<T>();
+        self.clone = Some(utils::clone_ptr::<T>);
+        self
+    }
+    /// Sets the function used to clone elements. It is passed a pointer to an element and must
+    /// write a clone of it to the destination pointer
+    ///
+    /// # Safety
+    /// `clone` must write a valid, independently owned value of the described type to its destination
+    pub unsafe fn with_clone_fn(mut self, clone: unsafe fn(*const u8, *mut u8)) -> Self {
+        self.clone = Some(clone);
+        self
+    }
+
+    /// Marks `T` as [`Copy`], allowing elements to be duplicated with a plain byte copy
+    #[track_caller]
+    pub fn with_copy<T: Copy + 'static>(mut self) -> Self {
+        self.assert_is::<T>();
+        self.copy = true;
+        self
+    }
+    /// Marks the described type as safe to duplicate with a plain byte copy
+    ///
+    /// # Safety
+    /// The described type must be [`Copy`], or behave as if it was
+    pub unsafe fn assume_copy(mut self) -> Self {
+        self.copy = true;
+        self
+    }
+
+    /// Marks `T` as plain old data, allowing vectors to be viewed as raw bytes.
+    /// This also marks it as [`Copy`]
+    #[cfg(feature = "bytemuck")]
+    #[track_caller]
+    pub fn with_pod<T: bytemuck::Pod>(mut self) -> Self {
+        self.assert_is::<T>();
+        self.copy = true;
+        self.pod = true;
+        self
+    }
+    /// Marks the described type as plain old data
+    ///
+    /// # Safety
+    /// The described type must uphold the requirements of [`bytemuck::Pod`]
+    #[cfg(feature = "bytemuck")]
+    pub unsafe fn assume_pod(mut self) -> Self {
+        self.copy = true;
+        self.pod = true;
+        self
+    }
+
+    /// Captures `T`'s [`Debug`](fmt::Debug) implementation, allowing vectors to be formatted without knowing `T`
+    #[track_caller]
+    pub fn with_debug<T: fmt::Debug + 'static>(mut self) -> Self {
+        self.assert_is::<T>();
+        self.debug = Some(utils::debug_ptr::<T>);
+        self
+    }
+    /// Sets the function used to format elements
+    ///
+    /// # Safety
+    /// `debug` must be sound to call on a pointer to any value of the described type
+    pub unsafe fn with_debug_fn(mut self, debug: DebugFn) -> Self {
+        self.debug = Some(debug);
+        self
+    }
+
+    /// Captures `T`'s [`Eq`] implementation, allowing vectors to be compared without knowing `T`
+    #[track_caller]
+    pub fn with_eq<T: Eq + 'static>(mut self) -> Self {
+        self.assert_is::<T>();
+        self.eq = Some(utils::eq_ptr::<T>);
+        self
+    }
+    /// Sets the function used to compare elements. It should be an equivalence relation,
+    /// as vectors using it implement [`Eq`]
+    ///
+    /// # Safety
+    /// `eq` must be sound to call on pointers to any two values of the described type
+    pub unsafe fn with_eq_fn(mut self, eq: EqFn) -> Self {
+        self.eq = Some(eq);
+        self
+    }
+
+    /// Captures `T`'s [`Hash`](std::hash::Hash) implementation, allowing vectors to be hashed without knowing `T`
+    #[track_caller]
+    pub fn with_hash<T: std::hash::Hash + 'static>(mut self) -> Self {
+        self.assert_is::<T>();
+        self.hash = Some(utils::hash_ptr::<T>);
+        self
+    }
+    /// Sets the function used to hash elements
+    ///
+    /// # Safety
+    /// `hash` must be sound to call on a pointer to any value of the described type
+    pub unsafe fn with_hash_fn(mut self, hash: HashFn) -> Self {
+        self.hash = Some(hash);
+        self
+    }
+
+    /// Captures `T`'s [`Ord`] implementation, allowing vectors to be sorted without knowing `T`
+    #[track_caller]
+    pub fn with_ord<T: Ord + 'static>(mut self) -> Self {
+        self.assert_is::<T>();
+        self.cmp = Some(utils::cmp_ptr::<T>);
+        self
+    }
+    /// Sets the function used to order elements
+    ///
+    /// # Safety
+    /// `cmp` must be sound to call on pointers to any two values of the described type
+    pub unsafe fn with_cmp_fn(mut self, cmp: CmpFn) -> Self {
+        self.cmp = Some(cmp);
+        self
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+    pub fn drop_fn(&self) -> Option<unsafe fn(*mut u8)> {
+        self.drop
+    }
+    pub fn type_id(&self) -> Option<TypeId> {
+        self.type_id
+    }
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+    pub fn clone_fn(&self) -> Option<unsafe fn(*const u8, *mut u8)> {
+        self.clone
+    }
+    pub fn is_copy(&self) -> bool {
+        self.copy
+    }
+    pub fn is_pod(&self) -> bool {
+        self.pod
+    }
+    pub fn debug_fn(&self) -> Option<DebugFn> {
+        self.debug
+    }
+    pub fn eq_fn(&self) -> Option<EqFn> {
+        self.eq
+    }
+    pub fn hash_fn(&self) -> Option<HashFn> {
+        self.hash
+    }
+    pub fn cmp_fn(&self) -> Option<CmpFn> {
+        self.cmp
+    }
+
+    /// Returns whether this describes the Rust type `T`
+    pub fn is<T: 'static>(&self) -> bool {
+        self.type_id == Some(TypeId::of::<T>())
+    }
+
+    /// Returns whether both descriptors describe the same type. Descriptors without a [`TypeId`]
+    /// are compared by name and layout, and never describe the same type as one with a [`TypeId`]
+    pub fn describes_same_type(&self, other: &ElementDescriptor) -> bool {
+        match (self.type_id, other.type_id) {
+            (Some(a), Some(b)) => a == b,
+            (None, None) => self.type_name == other.type_name && self.layout == other.layout,
+            _ => false,
+        }
+    }
+
+    pub(crate) fn check_type<T: 'static>(&self) -> Result<(), UntypedVecError> {
+        if self.is::<T>() {
+            Ok(())
+        } else {
+            Err(UntypedVecError::TypeMismatch {
+                expected: self.type_name,
+                found: type_name::<T>(),
+            })
+        }
+    }
+
+    /// Returns whether elements can be duplicated, by byte copy or with the clone function
+    pub(crate) fn is_cloneable(&self) -> bool {
+        self.copy || self.clone.is_some()
+    }
+
+    #[track_caller]
+    pub(crate) fn expect_eq_fn(&self) -> EqFn {
+        self.eq.unwrap_or_else(|| {
+            panic!(
+                "Vector of `{}` was created without an eq function",
+                self.type_name
+            )
+        })
+    }
+    #[track_caller]
+    pub(crate) fn expect_cmp_fn(&self) -> CmpFn {
+        self.cmp.unwrap_or_else(|| {
+            panic!(
+                "Vector of `{}` was created without a cmp function",
+                self.type_name
+            )
+        })
+    }
+    #[track_caller]
+    pub(crate) fn expect_hash_fn(&self) -> HashFn {
+        self.hash.unwrap_or_else(|| {
+            panic!(
+                "Vector of `{}` was created without a hash function",
+                self.type_name
+            )
+        })
+    }
+
+    #[track_caller]
+    fn assert_is<T: 'static>(&self) {
+        assert!(
+            self.is::<T>(),
+            "Type mismatch: descriptor describes `{}` but was given `{}`",
+            self.type_name,
+            type_name::<T>()
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use std::{alloc::Layout, fmt};
+
+/// The error type returned by the fallible `try_*` methods of [`UntypedVec`](crate::UntypedVec)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UntypedVecError {
+    /// The vector was accessed with a type other than the one it stores
+    TypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// The index was not less than the length of the vector
+    IndexOutOfBounds { index: usize, len: usize },
+    /// The new capacity would exceed what can be represented
+    CapacityOverflow,
+    /// The allocator failed to provide memory for the given layout
+    AllocError { layout: Layout },
+}
+
+impl fmt::Display for UntypedVecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UntypedVecError::TypeMismatch { expected, found } => write!(
+                f,
+                "Type mismatch: vector stores `{}` but was accessed as `{}`",
+                expected, found
+            ),
+            UntypedVecError::IndexOutOfBounds { index, len } => write!(
+                f,
+                "Index out of bounds: the len is {} but the index is {}",
+                len, index
+            ),
+            UntypedVecError::CapacityOverflow => write!(f, "Capacity overflow"),
+            UntypedVecError::AllocError { layout } => {
+                write!(f, "Failed to allocate memory for {:?}", layout)
+            }
+        }
+    }
+}
+
+impl std::error::Error for UntypedVecError {}
+
+impl From<TryReserveError> for UntypedVecError {
+    fn from(err: TryReserveError) -> Self {
+        match err {
+            TryReserveError::CapacityOverflow => UntypedVecError::CapacityOverflow,
+            TryReserveError::AllocError { layout } => UntypedVecError::AllocError { layout },
+        }
+    }
+}
+
+/// The error type returned by [`UntypedVec::try_reserve`](crate::UntypedVec::try_reserve)
+/// and [`UntypedVec::try_reserve_exact`](crate::UntypedVec::try_reserve_exact)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TryReserveError {
+    /// The new capacity would exceed what can be represented
+    CapacityOverflow,
+    /// The allocator failed to provide memory for the given layout
+    AllocError { layout: Layout },
+}
+
+impl fmt::Display for TryReserveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryReserveError::CapacityOverflow => write!(f, "Capacity overflow"),
+            TryReserveError::AllocError { layout } => {
+                write!(f, "Failed to allocate memory for {:?}", layout)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TryReserveError {}
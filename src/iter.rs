@@ -0,0 +1,224 @@
+use std::{iter::FusedIterator, marker::PhantomData};
+
+use crate::{RawAllocator, UntypedVec};
+
+/// A vector that [`IntoIter`] can move elements out of, either an [`UntypedVec`] or an
+/// [`UntypedSmallVec`](crate::UntypedSmallVec)
+pub trait Buffer: sealed::Buffer {}
+
+pub(crate) mod sealed {
+    pub trait Buffer {
+        /// Returns a pointer to the first element. May change when the buffer is moved
+        fn base(&self) -> *const u8;
+        fn base_mut(&mut self) -> *mut u8;
+        fn len_mut(&mut self) -> &mut usize;
+    }
+}
+
+impl<A: RawAllocator> Buffer for UntypedVec<A> {}
+impl<A: RawAllocator> sealed::Buffer for UntypedVec<A> {
+    fn base(&self) -> *const u8 {
+        self.ptr().as_ptr()
+    }
+    fn base_mut(&mut self) -> *mut u8 {
+        self.ptr().as_ptr()
+    }
+    fn len_mut(&mut self) -> &mut usize {
+        &mut self.len
+    }
+}
+
+/// An iterator that moves elements of type `T` out of an [`UntypedVec`] or an
+/// [`UntypedSmallVec`](crate::UntypedSmallVec)
+///
+/// Created by [`UntypedVec::into_iter`] and [`UntypedSmallVec::into_iter`](crate::UntypedSmallVec::into_iter)
+pub struct IntoIter<T, V: Buffer = UntypedVec> {
+    // The vector's len is kept at 0, so dropping it only frees the buffer
+    vec: V,
+    start: usize,
+    end: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T, V: Buffer> IntoIter<T, V> {
+    /// # Safety
+    /// The elements of `vec` must be of type `T`
+    pub(crate) unsafe fn new(mut vec: V) -> Self {
+        let len = vec.len_mut();
+        let end = *len;
+        *len = 0;
+
+        Self {
+            vec,
+            start: 0,
+            end,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the remaining elements as a slice
+    pub fn as_slice(&self) -> &[T] {
+        unsafe {
+            std::slice::from_raw_parts(
+                self.vec.base().cast::<T>().add(self.start),
+                self.end - self.start,
+            )
+        }
+    }
+
+    fn base(&mut self) -> *mut T {
+        self.vec.base_mut().cast::<T>()
+    }
+}
+
+impl<T, V: Buffer> Iterator for IntoIter<T, V> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.start == self.end {
+            None
+        } else {
+            let value = unsafe { std::ptr::read(self.base().add(self.start)) };
+            self.start += 1;
+            Some(value)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.end - self.start;
+        (len, Some(len))
+    }
+}
+
+impl<T, V: Buffer> DoubleEndedIterator for IntoIter<T, V> {
+    fn next_back(&mut self) -> Option<T> {
+        if self.start == self.end {
+            None
+        } else {
+            self.end -= 1;
+            Some(unsafe { std::ptr::read(self.base().add(self.end)) })
+        }
+    }
+}
+
+impl<T, V: Buffer> ExactSizeIterator for IntoIter<T, V> {}
+impl<T, V: Buffer> FusedIterator for IntoIter<T, V> {}
+
+impl<T, V: Buffer> Drop for IntoIter<T, V> {
+    fn drop(&mut self) {
+        let remaining = std::ptr::slice_from_raw_parts_mut(
+            unsafe { self.base().add(self.start) },
+            self.end - self.start,
+        );
+        self.start = self.end;
+        // The buffer itself is freed when `self.vec` is dropped, even if this panics
+        unsafe { std::ptr::drop_in_place(remaining) }
+    }
+}
+
+/// A draining iterator over a range of elements of type `T` in an [`UntypedVec`] or an
+/// [`UntypedSmallVec`](crate::UntypedSmallVec)
+///
+/// Created by [`UntypedVec::drain`] and [`UntypedSmallVec::drain`](crate::UntypedSmallVec::drain)
+pub struct Drain<'a, T> {
+    base: *mut T,
+    // Points to the len of the vector, which is kept at the start of the range until the drain is dropped
+    len: &'a mut usize,
+    idx: usize,
+    end: usize,
+    tail_start: usize,
+    tail_len: usize,
+    _marker: PhantomData<&'a mut T>,
+}
+
+impl<'a, T> Drain<'a, T> {
+    /// # Safety
+    /// `base` must point to `*len` initialized elements of type `T`, which stay in place for `'a`,
+    /// and `start..end` must be in bounds
+    pub(crate) unsafe fn new(base: *mut T, len: &'a mut usize, start: usize, end: usize) -> Self {
+        let tail_len = *len - end;
+        // Anything past `start` is owned by the drain until it is dropped
+        *len = start;
+
+        Self {
+            base,
+            len,
+            idx: start,
+            end,
+            tail_start: end,
+            tail_len,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the elements that have not been yielded yet as a slice
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { std::slice::from_raw_parts(self.base.add(self.idx), self.end - self.idx) }
+    }
+}
+
+impl<T> Iterator for Drain<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.idx == self.end {
+            None
+        } else {
+            let value = unsafe { std::ptr::read(self.base.add(self.idx)) };
+            self.idx += 1;
+            Some(value)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.end - self.idx;
+        (len, Some(len))
+    }
+}
+
+impl<T> DoubleEndedIterator for Drain<'_, T> {
+    fn next_back(&mut self) -> Option<T> {
+        if self.idx == self.end {
+            None
+        } else {
+            self.end -= 1;
+            Some(unsafe { std::ptr::read(self.base.add(self.end)) })
+        }
+    }
+}
+
+impl<T> ExactSizeIterator for Drain<'_, T> {}
+impl<T> FusedIterator for Drain<'_, T> {}
+
+impl<T> Drop for Drain<'_, T> {
+    fn drop(&mut self) {
+        // Moves the tail back into place, even if dropping the remaining elements panics
+        struct MoveTail<'r, 'a, T>(&'r mut Drain<'a, T>);
+
+        impl<T> Drop for MoveTail<'_, '_, T> {
+            fn drop(&mut self) {
+                let drain = &mut *self.0;
+                let start = *drain.len;
+                if drain.tail_len > 0 && drain.tail_start != start {
+                    unsafe {
+                        std::ptr::copy(
+                            drain.base.add(drain.tail_start),
+                            drain.base.add(start),
+                            drain.tail_len,
+                        )
+                    };
+                }
+                *drain.len = start + drain.tail_len;
+            }
+        }
+
+        let remaining = std::ptr::slice_from_raw_parts_mut(
+            unsafe { self.base.add(self.idx) },
+            self.end - self.idx,
+        );
+        self.idx = self.end;
+
+        let _guard = MoveTail(self);
+        unsafe { std::ptr::drop_in_place(remaining) }
+    }
+}
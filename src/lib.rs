@@ -1,119 +1,828 @@
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
+
+mod allocator;
+mod descriptor;
+mod error;
+mod iter;
+#[cfg(feature = "serde")]
+mod registry;
+mod small_vec;
+mod sparse_set;
+mod table;
+mod tracked;
+mod type_map;
 mod utils;
 
 use std::{
-    alloc::{handle_alloc_error, Layout},
-    ops::Add,
+    alloc::Layout,
+    fmt,
+    hash::{Hash, Hasher},
+    mem::{ManuallyDrop, MaybeUninit},
+    ops::{Range, RangeBounds},
     ptr::NonNull,
 };
 
 use crate::utils::array_layout;
 
+pub use crate::{
+    allocator::{Global, RawAllocator},
+    descriptor::{CmpFn, DebugFn, ElementDescriptor, EqFn, HashFn},
+    error::{TryReserveError, UntypedVecError},
+    iter::{Buffer, Drain, IntoIter},
+    small_vec::UntypedSmallVec,
+    sparse_set::SparseSet,
+    table::{Row, Table},
+    tracked::{Tick, TrackedVec},
+    type_map::TypeMap,
+};
+
+#[cfg(feature = "allocator_api")]
+pub use crate::allocator::AllocatorAdapter;
+#[cfg(feature = "serde")]
+pub use crate::registry::{SerializableVec, TypeRegistry, VecSeed};
+
 /// A type-erased version of the standard [`Vec`]
-pub struct UntypedVec {
+///
+/// The buffer is allocated with `A`, which defaults to the global allocator
+pub struct UntypedVec<A: RawAllocator = Global> {
     ptr: NonNull<u8>,
     capacity: usize,
     len: usize,
-    layout: Layout,
-    drop: unsafe fn(*mut u8),
+    desc: ElementDescriptor,
+    // The alignment of the buffer, which may exceed the alignment of the elements
+    align: usize,
+    alloc: A,
 }
 
 impl UntypedVec {
-    pub fn new<T>() -> Self {
-        // We can  hold a usize::MAX amount of zero sized types
-        let layout = Layout::new::<T>();
-        let capacity = if layout.size() == 0 { usize::MAX } else { 0 };
+    pub fn new<T: 'static>() -> Self {
+        Self::from_descriptor(ElementDescriptor::of::<T>())
+    }
+
+    /// Creates an empty vector that can be cloned without knowing `T`
+    pub fn new_cloneable<T: Clone + 'static>() -> Self {
+        Self::from_descriptor(ElementDescriptor::of::<T>().with_clone::<T>())
+    }
+
+    /// Creates an empty vector whose elements are printed by its [`Debug`](fmt::Debug) implementation
+    pub fn new_debug<T: fmt::Debug + 'static>() -> Self {
+        Self::from_descriptor(ElementDescriptor::of::<T>().with_debug::<T>())
+    }
+
+    /// Creates an empty vector of plain old data, which can be viewed as raw bytes
+    #[cfg(feature = "bytemuck")]
+    pub fn new_pod<T: bytemuck::Pod>() -> Self {
+        Self::from_descriptor(ElementDescriptor::of::<T>().with_pod::<T>())
+    }
+
+    /// Creates a vector of `T` by copying its elements out of `bytes`, which needn't be aligned
+    ///
+    /// # Panics
+    /// Panics if the length of `bytes` isn't a multiple of the size of `T`
+    #[cfg(feature = "bytemuck")]
+    #[track_caller]
+    pub fn from_bytes<T: bytemuck::Pod>(bytes: &[u8]) -> Self {
+        let mut vec = Self::new_pod::<T>();
+        let size = std::mem::size_of::<T>();
+        if size == 0 {
+            assert!(
+                bytes.is_empty(),
+                "Can't read zero sized elements from bytes"
+            );
+            return vec;
+        }
+        assert!(
+            bytes.len() % size == 0,
+            "Byte length {} isn't a multiple of the size of `{}`",
+            bytes.len(),
+            std::any::type_name::<T>()
+        );
+
+        let len = bytes.len() / size;
+        vec.reserve_exact(len);
+        // SAFETY: Any bit pattern is a valid `T`, and there's room for `len` elements
+        unsafe {
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), vec.ptr().as_ptr(), bytes.len());
+        }
+        vec.len = len;
+        vec
+    }
+
+    /// Creates an empty vector for elements described by `desc`
+    pub fn from_descriptor(desc: ElementDescriptor) -> Self {
+        Self::from_descriptor_in(desc, Global)
+    }
+
+    pub fn with_capacity<T: 'static>(capacity: usize) -> Self {
+        Self::with_capacity_in::<T>(capacity, Global)
+    }
 
+    /// Creates an empty vector whose buffer is aligned to at least `align` bytes, for example to
+    /// run SIMD kernels over it. Elements stay tightly packed, `size_of::<T>()` bytes apart
+    ///
+    /// # Panics
+    /// Panics if `align` is not a power of two
+    #[track_caller]
+    pub fn with_alignment<T: 'static>(align: usize) -> Self {
+        Self::with_alignment_in::<T>(align, Global)
+    }
+
+    /// Creates an empty vector for elements described by `desc`, whose buffer is aligned to at
+    /// least `align` bytes, see [`UntypedVec::with_alignment`]
+    ///
+    /// # Panics
+    /// Panics if `align` is not a power of two
+    #[track_caller]
+    pub fn from_descriptor_with_alignment(desc: ElementDescriptor, align: usize) -> Self {
+        Self::from_descriptor_with_alignment_in(desc, align, Global)
+    }
+
+    /// Reassembles a vector from the parts taken by [`UntypedVec::into_raw_parts`]
+    ///
+    /// # Safety
+    /// The parts must have been taken from a vector created by [`UntypedVec::from_descriptor`] with `desc`
+    pub(crate) unsafe fn from_raw_parts(
+        ptr: NonNull<u8>,
+        len: usize,
+        capacity: usize,
+        desc: ElementDescriptor,
+    ) -> Self {
         Self {
-            ptr: NonNull::dangling(),
+            ptr,
             capacity,
-            len: 0,
-            layout,
-            drop: utils::drop_ptr::<T>,
+            len,
+            desc,
+            align: desc.layout.align(),
+            alloc: Global,
         }
     }
 
-    pub fn with_capacity<T>(capacity: usize) -> Self {
-        let mut vec = UntypedVec::new::<T>();
+    /// Takes the buffer, len and capacity out of the vector, without dropping or freeing anything
+    pub(crate) fn into_raw_parts(self) -> (NonNull<u8>, usize, usize) {
+        let vec = ManuallyDrop::new(self);
+        (vec.ptr, vec.len, vec.capacity)
+    }
+}
+
+impl<A: RawAllocator> UntypedVec<A> {
+    /// Creates an empty vector whose buffer will be allocated with `alloc`
+    pub fn new_in<T: 'static>(alloc: A) -> Self {
+        Self::from_descriptor_in(ElementDescriptor::of::<T>(), alloc)
+    }
+
+    /// Creates an empty vector for elements described by `desc`, whose buffer will be allocated with `alloc`
+    pub fn from_descriptor_in(desc: ElementDescriptor, alloc: A) -> Self {
+        Self::from_descriptor_with_alignment_in(desc, desc.layout.align(), alloc)
+    }
+
+    pub fn with_capacity_in<T: 'static>(capacity: usize, alloc: A) -> Self {
+        let mut vec = Self::new_in::<T>(alloc);
         vec.reserve_exact(capacity);
         vec
     }
 
+    /// Like [`UntypedVec::with_alignment`], but the buffer will be allocated with `alloc`
+    ///
+    /// # Panics
+    /// Panics if `align` is not a power of two
+    #[track_caller]
+    pub fn with_alignment_in<T: 'static>(align: usize, alloc: A) -> Self {
+        Self::from_descriptor_with_alignment_in(ElementDescriptor::of::<T>(), align, alloc)
+    }
+
+    /// Like [`UntypedVec::from_descriptor_with_alignment`], but the buffer will be allocated with `alloc`
+    ///
+    /// # Panics
+    /// Panics if `align` is not a power of two
+    #[track_caller]
+    pub fn from_descriptor_with_alignment_in(
+        desc: ElementDescriptor,
+        align: usize,
+        alloc: A,
+    ) -> Self {
+        assert!(
+            align.is_power_of_two(),
+            "Alignment {} is not a power of two",
+            align
+        );
+        let align = align.max(desc.layout.align());
+
+        // We can  hold a usize::MAX amount of zero sized types
+        let capacity = if desc.layout.size() == 0 {
+            usize::MAX
+        } else {
+            0
+        };
+
+        Self {
+            ptr: utils::dangling(align),
+            capacity,
+            len: 0,
+            desc,
+            align,
+            alloc,
+        }
+    }
+
+    /// Returns the alignment the buffer is guaranteed to have, which is at least the
+    /// alignment of the element type
+    pub fn buffer_alignment(&self) -> usize {
+        self.align
+    }
+
+    /// Returns the allocator the buffer is allocated with
+    pub fn allocator(&self) -> &A {
+        &self.alloc
+    }
+
+    /// Clones the vector element by element, if its descriptor has a clone function or is marked as [`Copy`].
+    /// The clone is allocated with a clone of the allocator
+    pub fn try_clone(&self) -> Option<Self>
+    where
+        A: Clone,
+    {
+        self.try_clone_elements(0..self.len())
+    }
+
+    /// Returns the name of the element type, as given by [`std::any::type_name`]
+    pub fn type_name(&self) -> &'static str {
+        self.desc.type_name
+    }
+    /// Returns whether the elements of this vector are of type `T`
+    pub fn is<T: 'static>(&self) -> bool {
+        self.desc.is::<T>()
+    }
+    /// Returns the descriptor of the element type
+    pub fn descriptor(&self) -> &ElementDescriptor {
+        &self.desc
+    }
+
     pub fn capacity(&self) -> usize {
         self.capacity
     }
     pub fn len(&self) -> usize {
         self.len
     }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Reserves capacity for at least `additional` more elements, possibly more to
+    /// avoid frequent reallocations
+    #[track_caller]
+    pub fn reserve(&mut self, additional: usize) {
+        utils::handle_reserve(self.try_reserve(additional))
+    }
+    /// Like [`UntypedVec::reserve`], but returns an error instead of panicking or aborting
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let avail = self.capacity() - self.len();
+        if avail < additional {
+            self.try_grow_amortized(additional)
+        } else {
+            Ok(())
+        }
+    }
 
-    pub fn reserve_exact(&mut self, amount: usize) {
+    /// Reserves capacity for exactly `additional` more elements
+    #[track_caller]
+    pub fn reserve_exact(&mut self, additional: usize) {
+        utils::handle_reserve(self.try_reserve_exact(additional))
+    }
+    /// Like [`UntypedVec::reserve_exact`], but returns an error instead of panicking or aborting
+    pub fn try_reserve_exact(&mut self, additional: usize) -> Result<(), TryReserveError> {
         let avail = self.capacity() - self.len();
-        if avail < amount {
-            self.grow(amount)
+        if avail < additional {
+            self.try_grow(additional - avail)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Shrinks the capacity of the vector as much as possible
+    pub fn shrink_to_fit(&mut self) {
+        self.shrink_to(0)
+    }
+
+    /// Shrinks the capacity of the vector to the greater of `min_capacity` and its length.
+    /// Does nothing if the capacity is already lower
+    pub fn shrink_to(&mut self, min_capacity: usize) {
+        if self.stores_zst() {
+            return;
+        }
+
+        let new_capacity = self.len().max(min_capacity);
+        if new_capacity < self.capacity() {
+            utils::handle_reserve(self.try_realloc(new_capacity))
+        }
+    }
+
+    #[track_caller]
+    pub fn swap_remove<T: 'static>(&mut self, index: usize) -> T {
+        self.try_swap_remove(index)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+    pub fn try_swap_remove<T: 'static>(&mut self, index: usize) -> Result<T, UntypedVecError> {
+        self.check_type::<T>()?;
+        self.check_index(index)?;
+
+        let mut value = MaybeUninit::<T>::uninit();
+        unsafe {
+            self.swap_remove_raw(index, value.as_mut_ptr().cast::<u8>());
+            Ok(value.assume_init())
+        }
+    }
+
+    #[track_caller]
+    pub fn push<T: 'static>(&mut self, elem: T) {
+        self.try_push(elem)
+            .unwrap_or_else(|(_, err)| panic!("{}", err))
+    }
+    /// Pushes `elem` onto the vector, handing it back on failure
+    pub fn try_push<T: 'static>(&mut self, elem: T) -> Result<(), (T, UntypedVecError)> {
+        if let Err(err) = self.check_type::<T>() {
+            return Err((elem, err));
+        }
+        if self.len() == self.capacity() {
+            if let Err(err) = self.try_grow_amortized(1) {
+                return Err((elem, err.into()));
+            }
+        }
+
+        // The bytes are moved into the buffer, so the source must not be dropped
+        let elem = ManuallyDrop::new(elem);
+        let ptr = utils::to_const_ptr(&*elem);
+        unsafe { self.push_ptr(ptr) };
+        Ok(())
+    }
+
+    #[track_caller]
+    pub fn pop<T: 'static>(&mut self) -> Option<T> {
+        self.try_pop().unwrap_or_else(|err| panic!("{}", err))
+    }
+    pub fn try_pop<T: 'static>(&mut self) -> Result<Option<T>, UntypedVecError> {
+        self.check_type::<T>()?;
+
+        let mut value = MaybeUninit::<T>::uninit();
+        unsafe {
+            if self.pop_raw(value.as_mut_ptr().cast::<u8>()) {
+                Ok(Some(value.assume_init()))
+            } else {
+                Ok(None)
+            }
+        }
+    }
+
+    #[track_caller]
+    pub fn get<T: 'static>(&self, index: usize) -> &T {
+        self.try_get(index).unwrap_or_else(|err| panic!("{}", err))
+    }
+    pub fn try_get<T: 'static>(&self, index: usize) -> Result<&T, UntypedVecError> {
+        self.check_type::<T>()?;
+        self.check_index(index)?;
+
+        unsafe { Ok(&*self.ptr_to(index).cast::<T>()) }
+    }
+
+    #[track_caller]
+    pub fn get_mut<T: 'static>(&mut self, index: usize) -> &mut T {
+        self.try_get_mut(index)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+    pub fn try_get_mut<T: 'static>(&mut self, index: usize) -> Result<&mut T, UntypedVecError> {
+        self.check_type::<T>()?;
+        self.check_index(index)?;
+
+        unsafe { Ok(&mut *self.ptr_to(index).cast::<T>()) }
+    }
+
+    /// Views the elements as a slice of `T`
+    #[track_caller]
+    pub fn as_slice<T: 'static>(&self) -> &[T] {
+        self.check_type::<T>()
+            .unwrap_or_else(|err| panic!("{}", err));
+
+        // SAFETY: The buffer is always aligned for `T`, and the first `len` elements are initialized
+        unsafe { std::slice::from_raw_parts(self.ptr().as_ptr().cast::<T>(), self.len()) }
+    }
+    /// Views the elements as a mutable slice of `T`
+    #[track_caller]
+    pub fn as_mut_slice<T: 'static>(&mut self) -> &mut [T] {
+        self.check_type::<T>()
+            .unwrap_or_else(|err| panic!("{}", err));
+
+        // SAFETY: The buffer is always aligned for `T`, and the first `len` elements are initialized
+        unsafe { std::slice::from_raw_parts_mut(self.ptr().as_ptr().cast::<T>(), self.len()) }
+    }
+
+    /// Returns the initialized elements as raw bytes
+    ///
+    /// # Panics
+    /// Panics if the element type wasn't described as plain old data
+    #[cfg(feature = "bytemuck")]
+    #[track_caller]
+    pub fn as_bytes(&self) -> &[u8] {
+        self.assert_pod();
+        // SAFETY: Plain old data has no padding, so all `len * size` bytes are initialized
+        unsafe {
+            std::slice::from_raw_parts(self.ptr().as_ptr(), self.len * self.desc.layout.size())
+        }
+    }
+    /// Returns the initialized elements as mutable raw bytes
+    ///
+    /// # Panics
+    /// Panics if the element type wasn't described as plain old data
+    #[cfg(feature = "bytemuck")]
+    #[track_caller]
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        self.assert_pod();
+        // SAFETY: As above, and any bit pattern written is a valid element
+        unsafe {
+            std::slice::from_raw_parts_mut(self.ptr().as_ptr(), self.len * self.desc.layout.size())
+        }
+    }
+
+    /// Returns an iterator over references to the elements
+    #[track_caller]
+    pub fn iter<T: 'static>(&self) -> std::slice::Iter<'_, T> {
+        self.as_slice().iter()
+    }
+    /// Returns an iterator over mutable references to the elements
+    #[track_caller]
+    pub fn iter_mut<T: 'static>(&mut self) -> std::slice::IterMut<'_, T> {
+        self.as_mut_slice().iter_mut()
+    }
+
+    /// Consumes the vector, returning an iterator that moves its elements out.
+    /// Elements that are not consumed are dropped along with the iterator
+    ///
+    /// [`IntoIterator`] can't be implemented, as the element type is only known by the caller
+    #[allow(clippy::should_implement_trait)]
+    #[track_caller]
+    pub fn into_iter<T: 'static>(self) -> IntoIter<T, Self> {
+        self.check_type::<T>()
+            .unwrap_or_else(|err| panic!("{}", err));
+        // SAFETY: The type was checked above
+        unsafe { IntoIter::new(self) }
+    }
+
+    /// Removes the elements in `range`, returning them through an iterator.
+    /// Elements that are not consumed are dropped, and the tail is shifted back when the iterator is dropped
+    #[track_caller]
+    pub fn drain<T: 'static, R>(&mut self, range: R) -> Drain<'_, T>
+    where
+        R: RangeBounds<usize>,
+    {
+        self.check_type::<T>()
+            .unwrap_or_else(|err| panic!("{}", err));
+        let Range { start, end } = utils::slice_range(range, self.len());
+        // SAFETY: The elements are of type `T`, and the range was checked against the len
+        unsafe { Drain::new(self.ptr.as_ptr().cast::<T>(), &mut self.len, start, end) }
+    }
+
+    /// Inserts `elem` at `index`, shifting all elements after it to the right
+    #[track_caller]
+    pub fn insert<T: 'static>(&mut self, index: usize, elem: T) {
+        self.check_type::<T>()
+            .unwrap_or_else(|err| panic!("{}", err));
+        assert!(
+            index <= self.len(),
+            "Insertion index (is {}) should be <= len (is {})",
+            index,
+            self.len()
+        );
+
+        self.reserve(1);
+        unsafe {
+            let ptr = self.ptr_to(index);
+            std::ptr::copy(
+                ptr,
+                ptr.add(self.desc.layout.size()),
+                (self.len() - index) * self.desc.layout.size(),
+            );
+            std::ptr::write(ptr.cast::<T>(), elem);
         }
+        self.len += 1;
     }
 
-    pub fn swap_remove<T>(&mut self, index: usize) -> T {
-        assert!(index < self.len());
-        assert_eq!(Layout::new::<T>(), self.layout);
+    /// Removes and returns the element at `index`, shifting all elements after it to the left
+    #[track_caller]
+    pub fn remove<T: 'static>(&mut self, index: usize) -> T {
+        self.check_type::<T>()
+            .and_then(|_| self.check_index(index))
+            .unwrap_or_else(|err| panic!("{}", err));
 
         unsafe {
-            let value = std::ptr::read(self.ptr_to(index).cast::<T>());
+            let ptr = self.ptr_to(index);
+            let value = std::ptr::read(ptr.cast::<T>());
             std::ptr::copy(
-                self.ptr_to(self.len() - 1),
-                self.ptr_to(index),
-                self.layout.size(),
+                ptr.add(self.desc.layout.size()),
+                ptr,
+                (self.len() - index - 1) * self.desc.layout.size(),
             );
             self.len -= 1;
             value
         }
     }
 
-    pub fn push<T>(&mut self, elem: T) {
-        assert_eq!(Layout::new::<T>(), self.layout);
+    /// Shortens the vector to `len` elements, dropping the rest.
+    /// Does nothing if `len` is greater than the current length
+    pub fn truncate(&mut self, len: usize) {
+        // SAFETY: The first `self.len` elements are initialized
+        unsafe { utils::truncate(&self.desc, self.ptr.as_ptr(), &mut self.len, len) }
+    }
 
-        let ptr = utils::to_const_ptr(&elem);
-        unsafe { self.push_ptr(ptr) };
+    pub fn clear(&mut self) {
+        self.truncate(0)
     }
-    pub fn pop<T>(&mut self) -> Option<T> {
-        assert_eq!(Layout::new::<T>(), self.layout);
-        if self.len() == 0 {
-            None
-        } else {
-            self.len -= 1;
-            unsafe {
-                Some(std::ptr::read(
-                    self.ptr().as_ptr().cast::<T>().add(self.len()),
-                ))
-            }
+
+    /// Retains only the elements for which `f` returns true, preserving their order
+    #[track_caller]
+    pub fn retain<T: 'static, F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.retain_mut(|elem: &mut T| f(elem))
+    }
+
+    /// Retains only the elements for which `f` returns true, preserving their order
+    #[track_caller]
+    pub fn retain_mut<T: 'static, F>(&mut self, f: F)
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        self.check_type::<T>()
+            .unwrap_or_else(|err| panic!("{}", err));
+
+        // SAFETY: The elements are of type `T`, and the first `self.len` are initialized
+        unsafe { utils::retain_mut(self.ptr.as_ptr().cast::<T>(), &mut self.len, f) }
+    }
+
+    /// Removes all but the first of consecutive elements for which `same_bucket` returns true.
+    /// `same_bucket` is passed the element in question and the last element that was kept
+    #[track_caller]
+    pub fn dedup_by<T: 'static, F>(&mut self, same_bucket: F)
+    where
+        F: FnMut(&mut T, &mut T) -> bool,
+    {
+        self.check_type::<T>()
+            .unwrap_or_else(|err| panic!("{}", err));
+
+        // SAFETY: As in `retain_mut`
+        unsafe { utils::dedup_by(self.ptr.as_ptr().cast::<T>(), &mut self.len, same_bucket) }
+    }
+
+    /// Returns whether the vector contains an element equal to the one behind `elem`
+    ///
+    /// # Panics
+    /// Panics if the descriptor has no eq function
+    ///
+    /// # Safety
+    /// `elem` must point to a valid value of the element type
+    #[track_caller]
+    pub unsafe fn contains_raw(&self, elem: *const u8) -> bool {
+        self.position_raw(elem).is_some()
+    }
+
+    /// Returns the index of the first element equal to the one behind `elem`
+    ///
+    /// # Panics
+    /// Panics if the descriptor has no eq function
+    ///
+    /// # Safety
+    /// `elem` must point to a valid value of the element type
+    #[track_caller]
+    pub unsafe fn position_raw(&self, elem: *const u8) -> Option<usize> {
+        utils::position(&self.desc, self.ptr().as_ptr(), self.len(), elem)
+    }
+
+    /// Sorts the vector with the descriptor's cmp function, preserving the order of equal elements
+    ///
+    /// # Panics
+    /// Panics if the descriptor has no cmp function
+    #[track_caller]
+    pub fn sort(&mut self) {
+        unsafe {
+            let permutation =
+                utils::sort_permutation(&self.desc, self.ptr().as_ptr(), self.len(), true);
+            utils::permute(&self.desc, self.ptr().as_ptr(), &permutation)
         }
     }
 
-    pub fn get<T>(&self, index: usize) -> &T {
-        assert_eq!(Layout::new::<T>(), self.layout);
-        assert!(index < self.len());
+    /// Sorts the vector with the descriptor's cmp function, without preserving the order of equal elements
+    ///
+    /// # Panics
+    /// Panics if the descriptor has no cmp function
+    #[track_caller]
+    pub fn sort_unstable(&mut self) {
+        unsafe {
+            let permutation =
+                utils::sort_permutation(&self.desc, self.ptr().as_ptr(), self.len(), false);
+            utils::permute(&self.desc, self.ptr().as_ptr(), &permutation)
+        }
+    }
 
-        unsafe { &*self.ptr_to(index).cast::<T>() }
+    /// Returns the permutation that stably sorts the vector by the key `f` extracts from each element,
+    /// without moving any elements. Index `i` of the result holds the index of the element that
+    /// belongs at position `i`
+    pub fn sort_by_key_raw<K, F>(&self, f: F) -> Vec<usize>
+    where
+        K: Ord,
+        F: FnMut(*const u8) -> K,
+    {
+        unsafe { utils::sort_by_key(&self.desc, self.ptr().as_ptr(), self.len(), f) }
     }
-    pub fn get_mut<T>(&mut self, index: usize) -> &mut T {
-        assert_eq!(Layout::new::<T>(), self.layout);
-        assert!(index < self.len());
 
-        unsafe { &mut *self.ptr_to(index).cast::<T>() }
+    /// Binary searches a sorted vector for the element behind `elem`, like [`slice::binary_search`]
+    ///
+    /// # Panics
+    /// Panics if the descriptor has no cmp function
+    ///
+    /// # Safety
+    /// `elem` must point to a valid value of the element type
+    #[track_caller]
+    pub unsafe fn binary_search_raw(&self, elem: *const u8) -> Result<usize, usize> {
+        utils::binary_search(&self.desc, self.ptr().as_ptr(), self.len(), elem)
     }
 
-    pub fn clear(&mut self) {
-        let len = self.len;
-        self.len = 0;
-        for i in 0..len {
+    /// Reorders the elements so that the element at index `permutation[i]` ends up at index `i`.
+    /// Accepts the permutations produced by [`UntypedVec::sort_by_key_raw`], so one sort order can
+    /// be applied to several parallel vectors
+    ///
+    /// # Panics
+    /// Panics if `permutation` is not a permutation of `0..self.len()`
+    #[track_caller]
+    pub fn apply_permutation(&mut self, permutation: &[usize]) {
+        utils::check_permutation(permutation, self.len());
+        unsafe { utils::permute(&self.desc, self.ptr().as_ptr(), permutation) }
+    }
+
+    /// Returns a new vector holding copies of the elements at `indices`, in that order
+    ///
+    /// # Panics
+    /// Panics if an index is out of bounds, or the descriptor has no clone function
+    /// and isn't marked as [`Copy`]
+    #[track_caller]
+    pub fn gather(&self, indices: &[usize]) -> Self
+    where
+        A: Clone,
+    {
+        self.try_clone_elements(indices.iter().copied())
+            .unwrap_or_else(|| {
+                panic!(
+                    "Vector of `{}` was created without a clone function",
+                    self.desc.type_name
+                )
+            })
+    }
+
+    /// Moves the elements of `src` into this vector, so that element `i` of `src` replaces
+    /// the element at `indices[i]`. Replaced elements are dropped
+    ///
+    /// # Panics
+    /// Panics if `src` stores a different type, its len doesn't match the number of indices,
+    /// or an index is out of bounds
+    #[track_caller]
+    pub fn scatter<B: RawAllocator>(&mut self, indices: &[usize], mut src: UntypedVec<B>) {
+        utils::check_scatter(&self.desc, self.len(), indices, &src.desc, src.len());
+
+        // Ownership moves out of `src` up front, if a destructor panics the rest are leaked
+        src.len = 0;
+        unsafe { utils::scatter(&self.desc, self.ptr().as_ptr(), indices, src.ptr().as_ptr()) }
+    }
+
+    /// Removes the element at `index` and drops it, replacing it with the last element.
+    /// Unlike [`UntypedVec::swap_remove`], this doesn't need to know the element type
+    #[track_caller]
+    pub fn swap_remove_drop(&mut self, index: usize) {
+        self.check_index(index)
+            .unwrap_or_else(|err| panic!("{}", err));
+
+        let last = self.len() - 1;
+        if index != last {
             unsafe {
-                let ptr = self.ptr_to(i);
-                (self.drop)(ptr);
-            }
+                std::ptr::swap_nonoverlapping(
+                    self.ptr_to(index),
+                    self.ptr_to(last),
+                    self.desc.layout.size(),
+                )
+            };
+        }
+        self.truncate(last);
+    }
+
+    /// Returns the layout of a single element
+    pub fn element_layout(&self) -> Layout {
+        self.desc.layout()
+    }
+
+    /// Moves an element into the vector by copying `element_layout().size()` bytes from `src`
+    ///
+    /// # Safety
+    /// `src` must be valid for reads of `element_layout().size()` bytes and point to a valid value
+    /// of the element type. Ownership of that value moves into the vector, so the caller must not
+    /// drop or otherwise use it afterwards
+    #[track_caller]
+    pub unsafe fn push_raw(&mut self, src: *const u8) {
+        self.push_ptr(src)
+    }
+
+    /// Returns a pointer to the element at `index`.
+    /// The pointer is invalidated by any operation that moves elements or reallocates the buffer
+    #[track_caller]
+    pub fn get_raw(&self, index: usize) -> *const u8 {
+        self.check_index(index)
+            .unwrap_or_else(|err| panic!("{}", err));
+        unsafe { self.ptr_to(index) }
+    }
+    /// Returns a mutable pointer to the element at `index`.
+    /// The pointer is invalidated by any operation that moves elements or reallocates the buffer
+    #[track_caller]
+    pub fn get_raw_mut(&mut self, index: usize) -> *mut u8 {
+        self.check_index(index)
+            .unwrap_or_else(|err| panic!("{}", err));
+        unsafe { self.ptr_to(index) }
+    }
+
+    /// Moves the element at `index` into `dst`, replacing it with the last element
+    ///
+    /// # Safety
+    /// `dst` must be valid for writes of `element_layout().size()` bytes, be suitably aligned for
+    /// the element type and must not overlap the vector's buffer. The caller takes ownership of the
+    /// value written to `dst`
+    #[track_caller]
+    pub unsafe fn swap_remove_raw(&mut self, index: usize, dst: *mut u8) {
+        self.check_index(index)
+            .unwrap_or_else(|err| panic!("{}", err));
+
+        let size = self.desc.layout.size();
+        std::ptr::copy_nonoverlapping(self.ptr_to(index), dst, size);
+        std::ptr::copy(self.ptr_to(self.len() - 1), self.ptr_to(index), size);
+        self.len -= 1;
+    }
+
+    /// Moves the last element into `dst`, returning false if the vector is empty
+    ///
+    /// # Safety
+    /// `dst` must be valid for writes of `element_layout().size()` bytes, be suitably aligned for
+    /// the element type and must not overlap the vector's buffer. The caller takes ownership of the
+    /// value written to `dst`
+    pub unsafe fn pop_raw(&mut self, dst: *mut u8) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+
+        self.len -= 1;
+        std::ptr::copy_nonoverlapping(self.ptr_to(self.len()), dst, self.desc.layout.size());
+        true
+    }
+
+    fn check_type<T: 'static>(&self) -> Result<(), UntypedVecError> {
+        self.desc.check_type::<T>()
+    }
+    fn check_index(&self, index: usize) -> Result<(), UntypedVecError> {
+        utils::check_index(index, self.len())
+    }
+
+    #[cfg(feature = "bytemuck")]
+    #[track_caller]
+    fn assert_pod(&self) {
+        assert!(
+            self.desc.pod,
+            "Vector of `{}` wasn't created for plain old data",
+            self.desc.type_name
+        );
+    }
+
+    /// Duplicates the elements at `indices` into a new vector, by byte copy for [`Copy`] types
+    /// and with the clone function otherwise. Returns `None` if neither is possible
+    #[track_caller]
+    fn try_clone_elements<I>(&self, indices: I) -> Option<Self>
+    where
+        A: Clone,
+        I: ExactSizeIterator<Item = usize>,
+    {
+        if !self.desc.is_cloneable() {
+            return None;
         }
+
+        let mut vec =
+            Self::from_descriptor_with_alignment_in(self.desc, self.align, self.alloc.clone());
+        vec.reserve_exact(indices.len());
+        unsafe {
+            utils::clone_elements(
+                &self.desc,
+                self.ptr().as_ptr(),
+                self.len(),
+                indices,
+                vec.ptr.as_ptr(),
+                &mut vec.len,
+            )
+        };
+        Some(vec)
     }
 
     fn stores_zst(&self) -> bool {
-        self.layout.size() == 0
+        self.desc.layout.size() == 0
     }
     fn ptr(&self) -> NonNull<u8> {
         self.ptr
@@ -124,47 +833,186 @@ impl UntypedVec {
     /// Index should be less than capacity.
     unsafe fn ptr_to(&self, index: usize) -> *mut u8 {
         debug_assert!(index < self.capacity());
-        self.ptr().as_ptr().add(index * self.layout.size())
+        self.ptr().as_ptr().add(index * self.desc.layout.size())
     }
 
-    fn grow(&mut self, amount: usize) {
-        // grow() should never be reached if storing a ZST. If it is, the len has managed to exceed usize::MAX
-        assert!(!self.stores_zst(), "Exceeded capacity");
+    fn try_grow(&mut self, amount: usize) -> Result<(), TryReserveError> {
+        // Growing a vector of ZSTs means the len would exceed usize::MAX
+        if self.stores_zst() {
+            return Err(TryReserveError::CapacityOverflow);
+        }
 
-        let new_capacity = self.capacity() + amount;
-        let new_layout = utils::array_layout(&self.layout, new_capacity)
-            .expect("Failed to create valid array layout");
+        let new_capacity = self
+            .capacity()
+            .checked_add(amount)
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        self.try_realloc(new_capacity)
+    }
+
+    /// Grows the buffer so that at least `additional` more elements fit,
+    /// at least doubling the capacity to amortize the cost of pushes
+    fn try_grow_amortized(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        if self.stores_zst() {
+            return Err(TryReserveError::CapacityOverflow);
+        }
+
+        let required = self
+            .len()
+            .checked_add(additional)
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        let new_capacity = required
+            .max(self.capacity().saturating_mul(2))
+            .max(utils::min_non_zero_capacity(&self.desc.layout));
+        self.try_grow(new_capacity - self.capacity())
+    }
+
+    /// Moves the buffer to an allocation fitting exactly `new_capacity` elements.
+    /// `new_capacity` must be at least `self.len()` and the vector must not store ZSTs
+    fn try_realloc(&mut self, new_capacity: usize) -> Result<(), TryReserveError> {
+        debug_assert!(new_capacity >= self.len());
+        debug_assert!(!self.stores_zst());
+
+        let new_layout = utils::array_layout(&self.desc.layout, new_capacity, self.align)
+            .ok_or(TryReserveError::CapacityOverflow)?;
 
         unsafe {
-            let new_ptr = {
-                if self.capacity == 0 {
-                    std::alloc::alloc(new_layout)
+            if self.capacity() == 0 {
+                if new_capacity == 0 {
+                    return Ok(());
+                }
+                self.ptr = self
+                    .alloc
+                    .allocate(new_layout)
+                    .ok_or(TryReserveError::AllocError { layout: new_layout })?;
+            } else {
+                let old_layout = array_layout(&self.desc.layout, self.capacity(), self.align)
+                    .expect("Failed to create valid array layout");
+                if new_capacity == 0 {
+                    self.alloc.deallocate(self.ptr(), old_layout);
+                    self.ptr = utils::dangling(self.align);
                 } else {
-                    let old_layout = array_layout(&self.layout, self.capacity())
-                        .expect("Failed to create valid array layout");
-                    std::alloc::realloc(self.ptr().as_ptr(), old_layout, new_layout.size())
+                    // On failure the old allocation is left untouched
+                    self.ptr = self
+                        .alloc
+                        .reallocate(self.ptr(), old_layout, new_layout.size())
+                        .ok_or(TryReserveError::AllocError { layout: new_layout })?;
                 }
-            };
-
-            self.ptr = NonNull::new(new_ptr).unwrap_or_else(|| handle_alloc_error(new_layout));
+            }
         }
 
         self.capacity = new_capacity;
+        Ok(())
     }
 
     /// # Safety
-    /// src should be a valid pointer for a read of `self.layout.size()`
+    /// src should be a valid pointer for a read of `self.desc.layout.size()`
     unsafe fn push_ptr(&mut self, src: *const u8) {
-        self.reserve_exact(1);
+        self.reserve(1);
         // SAFETY: Safe as we have reserved the next blob of memory
         let ptr = self.ptr_to(self.len());
-        std::ptr::copy_nonoverlapping(src, ptr, self.layout.size());
+        std::ptr::copy_nonoverlapping(src, ptr, self.desc.layout.size());
         self.len += 1;
     }
 }
 
+impl<A: RawAllocator + Clone> Clone for UntypedVec<A> {
+    /// # Panics
+    /// Panics if the vector was not created with a clone function, see [`UntypedVec::try_clone`]
+    #[track_caller]
+    fn clone(&self) -> Self {
+        self.try_clone().unwrap_or_else(|| {
+            panic!(
+                "Vector of `{}` was created without a clone function",
+                self.desc.type_name
+            )
+        })
+    }
+}
+
+impl<A: RawAllocator> fmt::Debug for UntypedVec<A> {
+    /// Prints the elements like a [`Vec`] would if the descriptor has a debug function,
+    /// and a summary of the vector otherwise
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.desc.debug {
+            Some(debug) => f
+                .debug_list()
+                .entries((0..self.len()).map(|i| utils::DebugElement {
+                    ptr: unsafe { self.ptr_to(i) },
+                    debug,
+                }))
+                .finish(),
+            None => f
+                .debug_struct("UntypedVec")
+                .field("type", &self.desc.type_name)
+                .field("len", &self.len())
+                .field("capacity", &self.capacity())
+                .finish(),
+        }
+    }
+}
+
+impl<A: RawAllocator, B: RawAllocator> PartialEq<UntypedVec<B>> for UntypedVec<A> {
+    /// Vectors are equal if they store the same type and their elements are pairwise equal
+    ///
+    /// # Panics
+    /// Panics if the vectors store the same type, but were created without an eq function
+    #[track_caller]
+    fn eq(&self, other: &UntypedVec<B>) -> bool {
+        if !self.desc.describes_same_type(&other.desc) {
+            return false;
+        }
+
+        unsafe {
+            utils::elements_eq(
+                &self.desc,
+                self.ptr().as_ptr(),
+                self.len(),
+                other.ptr().as_ptr(),
+                other.len(),
+            )
+        }
+    }
+}
+
+impl<A: RawAllocator> Eq for UntypedVec<A> {}
+
+impl<A: RawAllocator> Hash for UntypedVec<A> {
+    /// # Panics
+    /// Panics if the vector was created without a hash function
+    #[track_caller]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        unsafe { utils::hash_elements(&self.desc, self.ptr().as_ptr(), self.len(), state) }
+    }
+}
+
+impl<A: RawAllocator> Drop for UntypedVec<A> {
+    fn drop(&mut self) {
+        self.clear();
+
+        // Nothing was ever allocated for ZSTs or for an untouched vector
+        if self.stores_zst() || self.capacity() == 0 {
+            return;
+        }
+
+        let layout = array_layout(&self.desc.layout, self.capacity(), self.align)
+            .expect("Failed to create valid array layout");
+        unsafe { self.alloc.deallocate(self.ptr(), layout) }
+    }
+}
+
+#[cfg(test)]
 mod tests {
-    use crate::UntypedVec;
+    use std::{
+        alloc::Layout,
+        cell::Cell,
+        panic::{catch_unwind, AssertUnwindSafe},
+        ptr::NonNull,
+        rc::Rc,
+    };
+
+    use crate::{
+        ElementDescriptor, Global, RawAllocator, TryReserveError, UntypedVec, UntypedVecError,
+    };
 
     #[derive(Debug, PartialEq)]
     struct Foo {
@@ -174,6 +1022,7 @@ mod tests {
     struct Bar {
         i: u32,
     }
+    #[allow(clippy::upper_case_acronyms)]
     #[derive(Debug, PartialEq)]
     struct ZST;
 
@@ -196,7 +1045,7 @@ mod tests {
     #[test]
     fn push_zsts() {
         let mut vec = UntypedVec::new::<ZST>();
-        for i in 0..100 {
+        for _ in 0..100 {
             vec.push(ZST)
         }
 
@@ -204,7 +1053,7 @@ mod tests {
             assert_eq!(vec.get::<ZST>(i), &ZST)
         }
 
-        for i in 0..100 {
+        for _ in 0..100 {
             assert_eq!(vec.pop::<ZST>().unwrap(), ZST)
         }
     }
@@ -223,4 +1072,1269 @@ mod tests {
         assert_eq!(vec.swap_remove::<Foo>(0), Foo { i: 0 });
         assert_eq!(vec.get::<Foo>(0), &Foo { i: 99 });
     }
+
+    struct DropCounter {
+        count: Rc<Cell<usize>>,
+    }
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.count.set(self.count.get() + 1);
+        }
+    }
+
+    #[test]
+    fn drop_elements() {
+        let count = Rc::new(Cell::new(0));
+        let mut vec = UntypedVec::new::<DropCounter>();
+        for _ in 0..10 {
+            vec.push(DropCounter {
+                count: count.clone(),
+            });
+        }
+
+        drop(vec);
+        assert_eq!(count.get(), 10);
+    }
+
+    #[test]
+    fn drop_empty_and_zsts() {
+        drop(UntypedVec::new::<Bar>());
+        drop(UntypedVec::with_capacity::<Bar>(16));
+
+        let mut vec = UntypedVec::new::<ZST>();
+        for _ in 0..100 {
+            vec.push(ZST)
+        }
+        drop(vec);
+    }
+
+    #[test]
+    fn push_strings() {
+        let mut vec = UntypedVec::new::<String>();
+        for i in 0..100 {
+            vec.push(i.to_string())
+        }
+
+        for i in 0..100 {
+            assert_eq!(vec.get::<String>(i), &i.to_string())
+        }
+
+        vec.get_mut::<String>(0).push_str("foo");
+        assert_eq!(vec.swap_remove::<String>(0), "0foo");
+        assert_eq!(vec.get::<String>(0), "99");
+        assert_eq!(vec.pop::<String>().unwrap(), "98");
+    }
+
+    #[test]
+    fn push_boxes_and_vecs() {
+        let mut boxes = UntypedVec::new::<Box<usize>>();
+        let mut vecs = UntypedVec::new::<Vec<usize>>();
+        for i in 0..100 {
+            boxes.push(Box::new(i));
+            vecs.push(vec![i; i]);
+        }
+
+        for i in 0..100 {
+            assert_eq!(**boxes.get::<Box<usize>>(i), i);
+            assert_eq!(vecs.get::<Vec<usize>>(i), &vec![i; i]);
+        }
+    }
+
+    #[test]
+    fn push_rcs() {
+        let rc = Rc::new(42usize);
+        let mut vec = UntypedVec::new::<Rc<usize>>();
+        for _ in 0..10 {
+            vec.push(rc.clone())
+        }
+        assert_eq!(Rc::strong_count(&rc), 11);
+
+        let popped = vec.pop::<Rc<usize>>().unwrap();
+        assert_eq!(Rc::strong_count(&rc), 11);
+        drop(popped);
+        assert_eq!(Rc::strong_count(&rc), 10);
+
+        drop(vec.swap_remove::<Rc<usize>>(0));
+        assert_eq!(Rc::strong_count(&rc), 9);
+
+        vec.clear();
+        assert_eq!(Rc::strong_count(&rc), 1);
+        assert!(vec.is_empty());
+
+        vec.push(rc.clone());
+        drop(vec);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn push_does_not_drop() {
+        let count = Rc::new(Cell::new(0));
+        let mut vec = UntypedVec::new::<DropCounter>();
+        for _ in 0..10 {
+            vec.push(DropCounter {
+                count: count.clone(),
+            });
+        }
+        assert_eq!(count.get(), 0);
+
+        drop(vec.pop::<DropCounter>());
+        assert_eq!(count.get(), 1);
+        drop(vec.swap_remove::<DropCounter>(0));
+        assert_eq!(count.get(), 2);
+
+        drop(vec);
+        assert_eq!(count.get(), 10);
+    }
+
+    #[test]
+    fn type_queries() {
+        let vec = UntypedVec::new::<Foo>();
+        assert!(vec.is::<Foo>());
+        assert!(!vec.is::<usize>());
+        assert_eq!(vec.type_name(), std::any::type_name::<Foo>());
+    }
+
+    #[test]
+    #[should_panic(expected = "vector stores `u64` but was accessed as `f64`")]
+    fn mismatched_type_same_layout() {
+        let mut vec = UntypedVec::new::<u64>();
+        vec.push(42u64);
+        vec.get::<f64>(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Type mismatch")]
+    fn mismatched_push() {
+        let mut vec = UntypedVec::new::<Foo>();
+        vec.push(42usize);
+    }
+
+    #[test]
+    fn try_accessors() {
+        let mut vec = UntypedVec::new::<Foo>();
+        for i in 0..10 {
+            vec.try_push(Foo { i }).unwrap();
+        }
+
+        assert_eq!(vec.try_get::<Foo>(3), Ok(&Foo { i: 3 }));
+        assert_eq!(
+            vec.try_get::<Foo>(10),
+            Err(UntypedVecError::IndexOutOfBounds { index: 10, len: 10 })
+        );
+        assert_eq!(
+            vec.try_get_mut::<Bar>(0),
+            Err(UntypedVecError::TypeMismatch {
+                expected: std::any::type_name::<Foo>(),
+                found: std::any::type_name::<Bar>(),
+            })
+        );
+        assert_eq!(
+            vec.try_push(Bar { i: 0 }),
+            Err((
+                Bar { i: 0 },
+                UntypedVecError::TypeMismatch {
+                    expected: std::any::type_name::<Foo>(),
+                    found: std::any::type_name::<Bar>(),
+                }
+            ))
+        );
+
+        assert_eq!(vec.try_swap_remove::<Foo>(0), Ok(Foo { i: 0 }));
+        assert!(vec.try_swap_remove::<Foo>(9).is_err());
+        assert_eq!(vec.try_pop::<Foo>(), Ok(Some(Foo { i: 8 })));
+        assert!(vec.try_pop::<Bar>().is_err());
+        assert_eq!(vec.len(), 8);
+    }
+
+    #[test]
+    fn try_push_overflow() {
+        let mut vec = UntypedVec::new::<ZST>();
+        vec.len = usize::MAX;
+        assert_eq!(
+            vec.try_push(ZST),
+            Err((ZST, UntypedVecError::CapacityOverflow))
+        );
+        vec.len = 0;
+    }
+
+    #[test]
+    #[should_panic(expected = "Index out of bounds: the len is 1 but the index is 1")]
+    fn get_out_of_bounds() {
+        let mut vec = UntypedVec::new::<Foo>();
+        vec.push(Foo { i: 0 });
+        vec.get::<Foo>(1);
+    }
+
+    #[test]
+    fn amortized_growth() {
+        let mut vec = UntypedVec::new::<Foo>();
+        let mut reallocations = 0;
+        for i in 0..1000 {
+            let capacity = vec.capacity();
+            vec.push(Foo { i });
+            if vec.capacity() != capacity {
+                reallocations += 1;
+            }
+        }
+        assert!(reallocations <= 10);
+
+        for i in 0..1000 {
+            assert_eq!(vec.get::<Foo>(i), &Foo { i })
+        }
+    }
+
+    #[test]
+    fn reserve() {
+        let mut vec = UntypedVec::with_capacity::<Foo>(10);
+        assert_eq!(vec.capacity(), 10);
+
+        vec.reserve_exact(5);
+        assert_eq!(vec.capacity(), 10);
+
+        for i in 0..10 {
+            vec.push(Foo { i })
+        }
+        vec.reserve_exact(5);
+        assert_eq!(vec.capacity(), 15);
+
+        vec.reserve(6);
+        assert!(vec.capacity() >= 30);
+    }
+
+    #[test]
+    fn shrink() {
+        let mut vec = UntypedVec::with_capacity::<String>(100);
+        for i in 0..10 {
+            vec.push(i.to_string())
+        }
+
+        vec.shrink_to(20);
+        assert_eq!(vec.capacity(), 20);
+        vec.shrink_to(50);
+        assert_eq!(vec.capacity(), 20);
+        vec.shrink_to_fit();
+        assert_eq!(vec.capacity(), 10);
+
+        for i in 0..10 {
+            assert_eq!(vec.get::<String>(i), &i.to_string())
+        }
+
+        vec.clear();
+        vec.shrink_to_fit();
+        assert_eq!(vec.capacity(), 0);
+        vec.push(String::from("foo"));
+        assert_eq!(vec.get::<String>(0), "foo");
+
+        let mut zsts = UntypedVec::new::<ZST>();
+        zsts.shrink_to_fit();
+        assert_eq!(zsts.capacity(), usize::MAX);
+    }
+
+    #[test]
+    fn try_reserve() {
+        let mut vec = UntypedVec::new::<Foo>();
+        assert_eq!(vec.try_reserve_exact(10), Ok(()));
+        assert_eq!(vec.capacity(), 10);
+        assert_eq!(vec.try_reserve(11), Ok(()));
+        assert!(vec.capacity() >= 20);
+
+        assert_eq!(
+            vec.try_reserve(usize::MAX),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        assert_eq!(
+            vec.try_reserve_exact(usize::MAX / 2),
+            Err(TryReserveError::CapacityOverflow)
+        );
+
+        // Fits in a usize, but not in an isize
+        let mut bytes = UntypedVec::new::<u8>();
+        assert_eq!(
+            bytes.try_reserve_exact(usize::MAX - 1),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        assert_eq!(
+            bytes.try_reserve_exact(isize::MAX as usize + 1),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        let mut aligned = UntypedVec::with_alignment::<u8>(64);
+        assert_eq!(
+            aligned.try_reserve_exact(isize::MAX as usize - 1),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        assert_eq!(bytes.capacity(), 0);
+
+        let mut zsts = UntypedVec::new::<ZST>();
+        assert_eq!(zsts.try_reserve_exact(usize::MAX), Ok(()));
+    }
+
+    #[test]
+    // Miri treats an allocation this large as resource exhaustion instead of a failure
+    #[cfg_attr(miri, ignore)]
+    fn try_reserve_alloc_error() {
+        // Small enough to pass the layout checks, far too big to ever be allocated
+        let mut vec = UntypedVec::new::<[u8; 1 << 20]>();
+        match vec.try_reserve_exact(1 << 42) {
+            Err(TryReserveError::AllocError { layout }) => {
+                assert_eq!(layout.size(), 1 << 62)
+            }
+            other => panic!("expected an allocation failure, got {:?}", other),
+        }
+        assert_eq!(vec.capacity(), 0);
+    }
+
+    /// Counts the bytes currently allocated through it
+    #[derive(Default)]
+    struct Tracker {
+        allocated: Cell<usize>,
+    }
+    unsafe impl RawAllocator for Tracker {
+        fn allocate(&self, layout: Layout) -> Option<NonNull<u8>> {
+            self.allocated.set(self.allocated.get() + layout.size());
+            Global.allocate(layout)
+        }
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.allocated.set(self.allocated.get() - layout.size());
+            Global.deallocate(ptr, layout)
+        }
+    }
+
+    #[test]
+    fn custom_allocator() {
+        let tracker = Tracker::default();
+        let mut vec = UntypedVec::from_descriptor_in(
+            ElementDescriptor::of::<String>().with_clone::<String>(),
+            &tracker,
+        );
+        for i in 0..10 {
+            vec.push(i.to_string());
+        }
+        assert_eq!(
+            tracker.allocated.get(),
+            vec.capacity() * size_of::<String>()
+        );
+
+        let clone = vec.clone();
+        assert_eq!(clone.iter::<String>().nth(3).unwrap(), "3");
+        vec.shrink_to_fit();
+        assert_eq!(
+            tracker.allocated.get(),
+            (10 + clone.capacity()) * size_of::<String>()
+        );
+
+        drop(clone);
+        let mut iter = vec.into_iter::<String>();
+        assert_eq!(iter.next().unwrap(), "0");
+        drop(iter);
+        assert_eq!(tracker.allocated.get(), 0);
+    }
+
+    #[test]
+    fn custom_allocator_failure() {
+        struct Exhausted;
+        unsafe impl RawAllocator for Exhausted {
+            fn allocate(&self, _layout: Layout) -> Option<NonNull<u8>> {
+                None
+            }
+            unsafe fn deallocate(&self, _ptr: NonNull<u8>, _layout: Layout) {
+                unreachable!()
+            }
+        }
+
+        let mut vec = UntypedVec::new_in::<u32>(Exhausted);
+        assert!(matches!(
+            vec.try_reserve(1),
+            Err(TryReserveError::AllocError { .. })
+        ));
+        assert_eq!(vec.capacity(), 0);
+    }
+
+    #[cfg(feature = "allocator_api")]
+    #[test]
+    fn allocator_api() {
+        let mut vec = UntypedVec::new_in::<usize>(crate::AllocatorAdapter(std::alloc::System));
+        for i in 0..100usize {
+            vec.push(i);
+        }
+        vec.drain::<usize, _>(10..);
+        vec.shrink_to_fit();
+        assert_eq!(vec.as_slice::<usize>(), (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn insert_remove() {
+        let mut vec = UntypedVec::new::<String>();
+        for i in 0..10 {
+            vec.insert(0, i.to_string())
+        }
+        vec.insert(10, String::from("end"));
+        vec.insert(5, String::from("middle"));
+
+        assert_eq!(vec.len(), 12);
+        assert_eq!(vec.get::<String>(0), "9");
+        assert_eq!(vec.get::<String>(5), "middle");
+        assert_eq!(vec.get::<String>(6), "4");
+        assert_eq!(vec.get::<String>(11), "end");
+
+        assert_eq!(vec.remove::<String>(5), "middle");
+        assert_eq!(vec.remove::<String>(10), "end");
+        assert_eq!(vec.remove::<String>(0), "9");
+        for i in 0..9 {
+            assert_eq!(vec.get::<String>(i), &(8 - i).to_string())
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Insertion index (is 2) should be <= len (is 1)")]
+    fn insert_out_of_bounds() {
+        let mut vec = UntypedVec::new::<Foo>();
+        vec.push(Foo { i: 0 });
+        vec.insert(2, Foo { i: 1 });
+    }
+
+    #[test]
+    fn truncate() {
+        let count = Rc::new(Cell::new(0));
+        let mut vec = UntypedVec::new::<DropCounter>();
+        for _ in 0..10 {
+            vec.push(DropCounter {
+                count: count.clone(),
+            });
+        }
+
+        vec.truncate(20);
+        assert_eq!(count.get(), 0);
+        vec.truncate(4);
+        assert_eq!(count.get(), 6);
+        assert_eq!(vec.len(), 4);
+        vec.truncate(0);
+        assert_eq!(count.get(), 10);
+        assert!(vec.is_empty());
+    }
+
+    #[test]
+    fn retain() {
+        let mut vec = UntypedVec::new::<String>();
+        for i in 0..100 {
+            vec.push(i.to_string())
+        }
+
+        vec.retain(|s: &String| s.ends_with('0'));
+        assert_eq!(vec.len(), 10);
+        for i in 0..10 {
+            assert_eq!(vec.get::<String>(i), &(i * 10).to_string())
+        }
+
+        vec.retain_mut(|s: &mut String| {
+            s.push('!');
+            s.len() > 2
+        });
+        assert_eq!(vec.len(), 9);
+        assert_eq!(vec.get::<String>(0), "10!");
+    }
+
+    #[test]
+    fn retain_panic_safety() {
+        let count = Rc::new(Cell::new(0));
+        let mut vec = UntypedVec::with_capacity::<DropCounter>(10);
+        for _ in 0..10 {
+            vec.push(DropCounter {
+                count: count.clone(),
+            });
+        }
+
+        let mut visited = 0;
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            vec.retain(|_: &DropCounter| {
+                visited += 1;
+                if visited == 6 {
+                    panic!("retain");
+                }
+                visited % 2 == 0
+            })
+        }));
+        assert!(result.is_err());
+
+        // Three of the five visited elements were rejected, the rest are kept
+        assert_eq!(count.get(), 3);
+        assert_eq!(vec.len(), 7);
+        drop(vec);
+        assert_eq!(count.get(), 10);
+    }
+
+    #[test]
+    fn dedup_by() {
+        let mut vec = UntypedVec::new::<Foo>();
+        for i in [1, 1, 2, 3, 3, 3, 1, 4, 4] {
+            vec.push(Foo { i })
+        }
+
+        vec.dedup_by(|a: &mut Foo, b: &mut Foo| a.i == b.i);
+        let expected = [1, 2, 3, 1, 4];
+        assert_eq!(vec.len(), expected.len());
+        for (index, i) in expected.into_iter().enumerate() {
+            assert_eq!(vec.get::<Foo>(index), &Foo { i })
+        }
+    }
+
+    #[test]
+    fn dedup_by_panic_safety() {
+        let count = Rc::new(Cell::new(0));
+        let mut vec = UntypedVec::with_capacity::<DropCounter>(10);
+        for _ in 0..10 {
+            vec.push(DropCounter {
+                count: count.clone(),
+            });
+        }
+
+        let mut calls = 0;
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            vec.dedup_by(|_: &mut DropCounter, _: &mut DropCounter| {
+                calls += 1;
+                if calls == 4 {
+                    panic!("dedup_by");
+                }
+                true
+            })
+        }));
+        assert!(result.is_err());
+
+        assert_eq!(count.get(), 3);
+        assert_eq!(vec.len(), 7);
+        drop(vec);
+        assert_eq!(count.get(), 10);
+    }
+
+    #[test]
+    fn slices() {
+        let mut vec = UntypedVec::new::<usize>();
+        assert!(vec.as_slice::<usize>().is_empty());
+
+        for i in [5usize, 3, 9, 1, 7] {
+            vec.push(i)
+        }
+        assert_eq!(vec.as_slice::<usize>(), &[5, 3, 9, 1, 7]);
+
+        vec.as_mut_slice::<usize>().sort();
+        assert_eq!(vec.as_slice::<usize>(), &[1, 3, 5, 7, 9]);
+        assert_eq!(vec.as_slice::<usize>().binary_search(&7), Ok(3));
+        assert_eq!(vec.get::<usize>(0), &1);
+
+        let mut zsts = UntypedVec::new::<ZST>();
+        zsts.push(ZST);
+        zsts.push(ZST);
+        assert_eq!(zsts.as_slice::<ZST>(), &[ZST, ZST]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Type mismatch")]
+    fn slice_mismatched_type() {
+        let vec = UntypedVec::new::<u32>();
+        vec.as_slice::<i32>();
+    }
+
+    #[test]
+    fn over_aligned_zsts() {
+        #[derive(Debug, PartialEq)]
+        #[repr(align(64))]
+        struct Aligned;
+
+        let mut vec = UntypedVec::new::<Aligned>();
+        assert_eq!(vec.as_slice::<Aligned>().as_ptr() as usize % 64, 0);
+        vec.push(Aligned);
+        assert_eq!(vec.get::<Aligned>(0) as *const Aligned as usize % 64, 0);
+    }
+
+    #[test]
+    fn over_aligned_buffer() {
+        let mut vec = UntypedVec::with_alignment::<f32>(64);
+        assert_eq!(vec.buffer_alignment(), 64);
+        for i in 0..100 {
+            vec.push(i as f32);
+            assert_eq!(vec.as_slice::<f32>().as_ptr() as usize % 64, 0);
+        }
+        vec.truncate(3);
+        vec.shrink_to_fit();
+        assert_eq!(vec.as_slice::<f32>().as_ptr() as usize % 64, 0);
+        // Elements stay tightly packed
+        assert_eq!(vec.get_raw(1) as usize - vec.get_raw(0) as usize, 4);
+        assert_eq!(vec.as_slice::<f32>(), [0.0, 1.0, 2.0]);
+
+        // Asking for less than the natural alignment changes nothing
+        assert_eq!(UntypedVec::with_alignment::<u64>(1).buffer_alignment(), 8);
+    }
+
+    #[test]
+    fn clone_keeps_alignment() {
+        let mut vec = UntypedVec::from_descriptor_with_alignment(
+            ElementDescriptor::of::<u8>().with_copy::<u8>(),
+            32,
+        );
+        vec.push(1u8);
+        let clone = vec.clone();
+        assert_eq!(clone.buffer_alignment(), 32);
+        assert_eq!(clone.as_slice::<u8>().as_ptr() as usize % 32, 0);
+    }
+
+    #[cfg(feature = "bytemuck")]
+    #[test]
+    fn over_aligned_pod_bytes() {
+        let mut vec = UntypedVec::from_descriptor_with_alignment(
+            ElementDescriptor::of::<f32>().with_pod::<f32>(),
+            64,
+        );
+        vec.push(1.0f32);
+        vec.push(2.0f32);
+        assert_eq!(vec.as_bytes().len(), 8);
+        assert_eq!(vec.as_bytes().as_ptr() as usize % 64, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "is not a power of two")]
+    fn alignment_not_power_of_two() {
+        UntypedVec::with_alignment::<u8>(24);
+    }
+
+    #[test]
+    fn iter() {
+        let mut vec = UntypedVec::new::<Foo>();
+        for i in 0..10 {
+            vec.push(Foo { i })
+        }
+
+        assert!(vec.iter::<Foo>().enumerate().all(|(i, foo)| foo.i == i));
+        for foo in vec.iter_mut::<Foo>() {
+            foo.i *= 2;
+        }
+        assert_eq!(vec.iter::<Foo>().map(|foo| foo.i).sum::<usize>(), 90);
+    }
+
+    #[test]
+    fn into_iter() {
+        let mut vec = UntypedVec::new::<String>();
+        for i in 0..10 {
+            vec.push(i.to_string())
+        }
+
+        let mut iter = vec.into_iter::<String>();
+        assert_eq!(iter.len(), 10);
+        assert_eq!(iter.next().unwrap(), "0");
+        assert_eq!(iter.next_back().unwrap(), "9");
+        assert_eq!(iter.as_slice().len(), 8);
+        let rest: Vec<String> = iter.collect();
+        assert_eq!(rest, (1..9).map(|i| i.to_string()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn into_iter_drops_remainder() {
+        let count = Rc::new(Cell::new(0));
+        let mut vec = UntypedVec::new::<DropCounter>();
+        for _ in 0..10 {
+            vec.push(DropCounter {
+                count: count.clone(),
+            });
+        }
+
+        let mut iter = vec.into_iter::<DropCounter>();
+        drop(iter.next());
+        drop(iter.next_back());
+        assert_eq!(count.get(), 2);
+        drop(iter);
+        assert_eq!(count.get(), 10);
+    }
+
+    #[test]
+    fn drain() {
+        let mut vec = UntypedVec::new::<String>();
+        for i in 0..10 {
+            vec.push(i.to_string())
+        }
+
+        let drained: Vec<String> = vec.drain::<String, _>(2..5).collect();
+        assert_eq!(drained, ["2", "3", "4"]);
+        assert_eq!(vec.len(), 7);
+        assert_eq!(
+            vec.as_slice::<String>(),
+            ["0", "1", "5", "6", "7", "8", "9"]
+        );
+
+        let mut drain = vec.drain::<String, _>(..=1);
+        assert_eq!(drain.next_back().unwrap(), "1");
+        drop(drain);
+        assert_eq!(vec.as_slice::<String>(), ["5", "6", "7", "8", "9"]);
+
+        vec.drain::<String, _>(3..);
+        assert_eq!(vec.as_slice::<String>(), ["5", "6", "7"]);
+        vec.drain::<String, _>(..);
+        assert!(vec.is_empty());
+    }
+
+    #[test]
+    fn drain_drops_remainder() {
+        let count = Rc::new(Cell::new(0));
+        let mut vec = UntypedVec::new::<DropCounter>();
+        for _ in 0..10 {
+            vec.push(DropCounter {
+                count: count.clone(),
+            });
+        }
+
+        let mut drain = vec.drain::<DropCounter, _>(2..8);
+        drop(drain.next());
+        assert_eq!(count.get(), 1);
+        drop(drain);
+        assert_eq!(count.get(), 6);
+        assert_eq!(vec.len(), 4);
+
+        drop(vec);
+        assert_eq!(count.get(), 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "range end index 11 out of range for slice of length 10")]
+    fn drain_out_of_bounds() {
+        let mut vec = UntypedVec::new::<usize>();
+        for i in 0..10usize {
+            vec.push(i)
+        }
+        vec.drain::<usize, _>(5..11);
+    }
+
+    #[test]
+    fn raw_elements() {
+        let mut vec = UntypedVec::new::<String>();
+        assert_eq!(vec.element_layout(), std::alloc::Layout::new::<String>());
+
+        for i in 0..10 {
+            let elem = std::mem::ManuallyDrop::new(i.to_string());
+            unsafe { vec.push_raw((&*elem as *const String).cast::<u8>()) };
+        }
+
+        for i in 0..10 {
+            let elem = unsafe { &*vec.get_raw(i).cast::<String>() };
+            assert_eq!(elem, &i.to_string());
+        }
+        unsafe { (*vec.get_raw_mut(0).cast::<String>()).push_str("foo") };
+
+        let mut dst = std::mem::MaybeUninit::<String>::uninit();
+        unsafe {
+            vec.swap_remove_raw(0, dst.as_mut_ptr().cast::<u8>());
+            assert_eq!(dst.assume_init_read(), "0foo");
+        }
+        assert_eq!(vec.get::<String>(0), "9");
+
+        unsafe {
+            assert!(vec.pop_raw(dst.as_mut_ptr().cast::<u8>()));
+            assert_eq!(dst.assume_init_read(), "8");
+        }
+
+        vec.clear();
+        assert!(!unsafe { vec.pop_raw(dst.as_mut_ptr().cast::<u8>()) });
+    }
+
+    #[test]
+    #[should_panic(expected = "Index out of bounds")]
+    fn raw_out_of_bounds() {
+        let vec = UntypedVec::new::<String>();
+        vec.get_raw(0);
+    }
+
+    #[test]
+    fn from_descriptor() {
+        let desc = ElementDescriptor::of::<String>();
+        assert_eq!(desc.layout(), Layout::new::<String>());
+        assert!(desc.drop_fn().is_some());
+        assert!(ElementDescriptor::of::<usize>().drop_fn().is_none());
+
+        let mut vec = UntypedVec::from_descriptor(desc);
+        assert!(vec.is::<String>());
+        vec.push(String::from("foo"));
+        assert_eq!(vec.get::<String>(0), "foo");
+    }
+
+    #[test]
+    fn runtime_descriptor() {
+        unsafe fn drop_counter(ptr: *mut u8) {
+            ptr.cast::<DropCounter>().drop_in_place()
+        }
+
+        let count = Rc::new(Cell::new(0));
+        let desc = unsafe {
+            ElementDescriptor::new("counter", Layout::new::<DropCounter>(), Some(drop_counter))
+        };
+        assert_eq!(desc.type_id(), None);
+
+        let mut vec = UntypedVec::from_descriptor(desc);
+        assert_eq!(vec.type_name(), "counter");
+        assert!(!vec.is::<DropCounter>());
+        assert!(vec.try_get::<DropCounter>(0).is_err());
+
+        for _ in 0..10 {
+            let elem = std::mem::ManuallyDrop::new(DropCounter {
+                count: count.clone(),
+            });
+            unsafe { vec.push_raw((&*elem as *const DropCounter).cast::<u8>()) };
+        }
+        vec.truncate(5);
+        assert_eq!(count.get(), 5);
+        drop(vec);
+        assert_eq!(count.get(), 10);
+
+        let typed = unsafe { desc.with_type_id(std::any::TypeId::of::<DropCounter>()) };
+        let mut vec = UntypedVec::from_descriptor(typed);
+        vec.push(DropCounter {
+            count: count.clone(),
+        });
+        assert!(vec.is::<DropCounter>());
+    }
+
+    #[test]
+    fn runtime_zst_descriptor() {
+        let desc = unsafe { ElementDescriptor::new("unit", Layout::new::<()>(), None) };
+        let mut vec = UntypedVec::from_descriptor(desc);
+        assert_eq!(vec.capacity(), usize::MAX);
+        unsafe { vec.push_raw(std::ptr::NonNull::<()>::dangling().as_ptr().cast::<u8>()) };
+        assert_eq!(vec.len(), 1);
+    }
+
+    #[test]
+    fn clone() {
+        let mut vec = UntypedVec::new_cloneable::<String>();
+        for i in 0..10 {
+            vec.push(i.to_string())
+        }
+
+        let mut cloned = vec.clone();
+        cloned.push(String::from("foo"));
+        vec.get_mut::<String>(0).push_str("bar");
+
+        assert_eq!(vec.len(), 10);
+        assert_eq!(cloned.len(), 11);
+        assert_eq!(vec.get::<String>(0), "0bar");
+        assert_eq!(cloned.get::<String>(0), "0");
+        assert!(cloned.try_clone().is_some());
+
+        assert!(UntypedVec::new::<String>().try_clone().is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "created without a clone function")]
+    fn clone_without_vtable() {
+        let _ = UntypedVec::new::<String>().clone();
+    }
+
+    #[test]
+    fn clone_panic_safety() {
+        struct PanicOnClone {
+            count: Rc<Cell<usize>>,
+            panic: bool,
+        }
+        impl Clone for PanicOnClone {
+            fn clone(&self) -> Self {
+                assert!(!self.panic, "clone");
+                PanicOnClone {
+                    count: self.count.clone(),
+                    panic: false,
+                }
+            }
+        }
+        impl Drop for PanicOnClone {
+            fn drop(&mut self) {
+                self.count.set(self.count.get() + 1);
+            }
+        }
+
+        let count = Rc::new(Cell::new(0));
+        let mut vec = UntypedVec::new_cloneable::<PanicOnClone>();
+        for i in 0..10 {
+            vec.push(PanicOnClone {
+                count: count.clone(),
+                panic: i == 5,
+            });
+        }
+
+        assert!(catch_unwind(AssertUnwindSafe(|| vec.try_clone())).is_err());
+        assert_eq!(count.get(), 5);
+        drop(vec);
+        assert_eq!(count.get(), 15);
+    }
+
+    #[test]
+    #[should_panic(expected = "descriptor describes `u32` but was given `i32`")]
+    fn clone_vtable_mismatch() {
+        ElementDescriptor::of::<u32>().with_clone::<i32>();
+    }
+
+    #[test]
+    fn debug() {
+        let mut vec = UntypedVec::new_debug::<Foo>();
+        assert_eq!(format!("{:?}", vec), "[]");
+        for i in 0..3 {
+            vec.push(Foo { i })
+        }
+        assert_eq!(
+            format!("{:?}", vec),
+            format!("{:?}", [Foo { i: 0 }, Foo { i: 1 }, Foo { i: 2 }])
+        );
+        assert_eq!(
+            format!("{:#?}", vec),
+            format!("{:#?}", [Foo { i: 0 }, Foo { i: 1 }, Foo { i: 2 }])
+        );
+
+        let mut vec = UntypedVec::with_capacity::<u8>(4);
+        vec.push(1u8);
+        assert_eq!(
+            format!("{:?}", vec),
+            "UntypedVec { type: \"u8\", len: 1, capacity: 4 }"
+        );
+    }
+
+    fn hashable<T: Eq + std::hash::Hash + 'static>() -> UntypedVec {
+        UntypedVec::from_descriptor(ElementDescriptor::of::<T>().with_eq::<T>().with_hash::<T>())
+    }
+
+    #[test]
+    fn eq() {
+        let mut a = hashable::<String>();
+        let mut b = hashable::<String>();
+        assert_eq!(a, b);
+        assert_ne!(a, hashable::<usize>());
+
+        for i in 0..10 {
+            a.push(i.to_string());
+            b.push(i.to_string());
+        }
+        assert_eq!(a, b);
+
+        b.get_mut::<String>(3).push('!');
+        assert_ne!(a, b);
+        b.pop::<String>();
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    #[should_panic(expected = "created without an eq function")]
+    fn eq_without_vtable() {
+        let _ = UntypedVec::new::<usize>() == UntypedVec::new::<usize>();
+    }
+
+    #[test]
+    fn eq_runtime_descriptor_with_same_name() {
+        let desc = unsafe {
+            ElementDescriptor::new(
+                std::any::type_name::<String>(),
+                Layout::new::<String>(),
+                None,
+            )
+        };
+        let mut raw = UntypedVec::from_descriptor(desc);
+        unsafe { raw.push_raw([0xffu8; size_of::<String>()].as_ptr()) };
+        let mut typed = hashable::<String>();
+        typed.push(String::new());
+
+        assert_ne!(typed, raw);
+        assert_ne!(raw, typed);
+    }
+
+    #[test]
+    fn hash() {
+        use std::hash::{BuildHasher, RandomState};
+
+        let state = RandomState::new();
+        let mut a = hashable::<String>();
+        let mut b = hashable::<String>();
+        assert_eq!(state.hash_one(&a), state.hash_one(&b));
+
+        for i in 0..10 {
+            a.push(i.to_string());
+            b.push(i.to_string());
+        }
+        assert_eq!(state.hash_one(&a), state.hash_one(&b));
+
+        b.swap_remove::<String>(0);
+        assert_ne!(state.hash_one(&a), state.hash_one(&b));
+
+        let columns: std::collections::HashSet<UntypedVec> = [a, b].into_iter().collect();
+        assert_eq!(columns.len(), 2);
+    }
+
+    #[test]
+    fn position_raw() {
+        let mut vec = hashable::<String>();
+        for i in 0..10 {
+            vec.push(i.to_string())
+        }
+
+        let needle = String::from("7");
+        let missing = String::from("foo");
+        unsafe {
+            let needle = (&needle as *const String).cast::<u8>();
+            let missing = (&missing as *const String).cast::<u8>();
+            assert_eq!(vec.position_raw(needle), Some(7));
+            assert!(vec.contains_raw(needle));
+            assert_eq!(vec.position_raw(missing), None);
+            assert!(!vec.contains_raw(missing));
+        }
+    }
+
+    fn sortable<T: Ord + 'static>() -> UntypedVec {
+        UntypedVec::from_descriptor(ElementDescriptor::of::<T>().with_ord::<T>())
+    }
+
+    #[test]
+    fn sort() {
+        let mut vec = sortable::<String>();
+        for i in [5, 3, 9, 1, 7, 3, 0] {
+            vec.push(i.to_string())
+        }
+
+        vec.sort();
+        assert_eq!(
+            vec.as_slice::<String>(),
+            ["0", "1", "3", "3", "5", "7", "9"]
+        );
+
+        let mut vec = sortable::<(u8, u64)>();
+        for i in 0..100u64 {
+            vec.push(((i * 7 % 10) as u8, i))
+        }
+        vec.sort_unstable();
+        assert!(vec
+            .as_slice::<(u8, u64)>()
+            .windows(2)
+            .all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn sort_is_stable() {
+        #[derive(Debug)]
+        struct Keyed(u8, usize);
+        impl PartialEq for Keyed {
+            fn eq(&self, other: &Self) -> bool {
+                self.0 == other.0
+            }
+        }
+        impl Eq for Keyed {}
+        impl PartialOrd for Keyed {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+        impl Ord for Keyed {
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                self.0.cmp(&other.0)
+            }
+        }
+
+        let mut vec = sortable::<Keyed>();
+        for i in 0..100 {
+            vec.push(Keyed((i % 3) as u8, i))
+        }
+        vec.sort();
+        assert!(vec
+            .as_slice::<Keyed>()
+            .windows(2)
+            .all(|pair| pair[0].0 < pair[1].0 || pair[0].1 < pair[1].1));
+    }
+
+    #[test]
+    #[should_panic(expected = "created without a cmp function")]
+    fn sort_without_vtable() {
+        UntypedVec::new::<usize>().sort();
+    }
+
+    #[test]
+    fn binary_search_raw() {
+        let mut vec = sortable::<usize>();
+        for i in [1usize, 3, 5, 7, 9] {
+            vec.push(i)
+        }
+
+        for (needle, expected) in [(5usize, Ok(2)), (0, Err(0)), (4, Err(2)), (10, Err(5))] {
+            let found = unsafe { vec.binary_search_raw((&needle as *const usize).cast::<u8>()) };
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn sort_by_key_raw() {
+        let mut vec = UntypedVec::new::<String>();
+        for s in ["ccc", "a", "bb", "dd"] {
+            vec.push(String::from(s))
+        }
+
+        let permutation = vec.sort_by_key_raw(|ptr| unsafe { (&*ptr.cast::<String>()).len() });
+        assert_eq!(permutation, [1, 2, 3, 0]);
+        assert_eq!(vec.get::<String>(0), "ccc");
+    }
+
+    #[test]
+    fn clone_copy_types() {
+        let mut vec =
+            UntypedVec::from_descriptor(ElementDescriptor::of::<u32>().with_copy::<u32>());
+        for i in 0..10u32 {
+            vec.push(i)
+        }
+        let cloned = vec.clone();
+        assert_eq!(cloned.as_slice::<u32>(), vec.as_slice::<u32>());
+    }
+
+    #[test]
+    fn apply_permutation() {
+        let mut names = sortable::<String>();
+        let mut ids = UntypedVec::new::<usize>();
+        for (id, name) in ["c", "a", "d", "b"].into_iter().enumerate() {
+            names.push(String::from(name));
+            ids.push(id);
+        }
+
+        let permutation = names.sort_by_key_raw(|ptr| unsafe { (*ptr.cast::<String>()).clone() });
+        names.apply_permutation(&permutation);
+        ids.apply_permutation(&permutation);
+
+        assert_eq!(names.as_slice::<String>(), ["a", "b", "c", "d"]);
+        assert_eq!(ids.as_slice::<usize>(), [1, 3, 0, 2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Index 1 is out of bounds or repeated in the permutation")]
+    fn apply_invalid_permutation() {
+        let mut vec = UntypedVec::new::<usize>();
+        for i in 0..3usize {
+            vec.push(i)
+        }
+        vec.apply_permutation(&[1, 1, 0]);
+    }
+
+    #[test]
+    fn gather() {
+        let mut vec = UntypedVec::new_cloneable::<String>();
+        for i in 0..10 {
+            vec.push(i.to_string())
+        }
+
+        let gathered = vec.gather(&[9, 0, 0, 4]);
+        assert_eq!(gathered.as_slice::<String>(), ["9", "0", "0", "4"]);
+        assert_eq!(vec.len(), 10);
+
+        let mut copies =
+            UntypedVec::from_descriptor(ElementDescriptor::of::<u64>().with_copy::<u64>());
+        for i in 0..10u64 {
+            copies.push(i * i)
+        }
+        assert_eq!(copies.gather(&[3, 2, 1]).as_slice::<u64>(), [9, 4, 1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "created without a clone function")]
+    fn gather_without_vtable() {
+        let mut vec = UntypedVec::new::<String>();
+        vec.push(String::new());
+        vec.gather(&[0]);
+    }
+
+    #[test]
+    fn scatter() {
+        let count = Rc::new(Cell::new(0));
+        let mut vec = UntypedVec::new::<DropCounter>();
+        let mut src = UntypedVec::new::<DropCounter>();
+        for _ in 0..10 {
+            vec.push(DropCounter {
+                count: count.clone(),
+            });
+        }
+        for _ in 0..3 {
+            src.push(DropCounter {
+                count: count.clone(),
+            });
+        }
+
+        vec.scatter(&[1, 5, 9], src);
+        assert_eq!(count.get(), 3);
+        assert_eq!(vec.len(), 10);
+        drop(vec);
+        assert_eq!(count.get(), 13);
+
+        let mut vec = UntypedVec::new::<String>();
+        let mut src = UntypedVec::new::<String>();
+        for i in 0..5 {
+            vec.push(i.to_string());
+        }
+        src.push(String::from("a"));
+        src.push(String::from("b"));
+        vec.scatter(&[4, 0], src);
+        assert_eq!(vec.as_slice::<String>(), ["b", "1", "2", "3", "a"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Type mismatch")]
+    fn scatter_mismatched_type() {
+        let mut vec = UntypedVec::new::<u32>();
+        vec.push(0u32);
+        vec.scatter(&[0], UntypedVec::new::<i32>());
+    }
+
+    #[test]
+    #[should_panic(expected = "Type mismatch")]
+    fn scatter_runtime_descriptor_into_typed() {
+        let desc = unsafe {
+            ElementDescriptor::new(
+                std::any::type_name::<String>(),
+                Layout::new::<String>(),
+                None,
+            )
+        };
+        let mut raw = UntypedVec::from_descriptor(desc);
+        unsafe { raw.push_raw([0xffu8; size_of::<String>()].as_ptr()) };
+
+        let mut vec = UntypedVec::new::<String>();
+        vec.push(String::from("a"));
+        vec.scatter(&[0], raw);
+    }
+
+    #[test]
+    fn swap_remove_drop() {
+        let mut vec = UntypedVec::new::<String>();
+        for i in 0..5 {
+            vec.push(i.to_string())
+        }
+
+        vec.swap_remove_drop(1);
+        assert_eq!(vec.as_slice::<String>(), ["0", "4", "2", "3"]);
+        vec.swap_remove_drop(3);
+        assert_eq!(vec.as_slice::<String>(), ["0", "4", "2"]);
+    }
+
+    #[cfg(feature = "bytemuck")]
+    #[test]
+    fn bytes_round_trip() {
+        let mut vec = UntypedVec::new_pod::<u32>();
+        for i in 0..4u32 {
+            vec.push(i);
+        }
+        assert_eq!(vec.as_bytes().len(), 16);
+        vec.as_bytes_mut()[0] = 7;
+
+        // Slicing by one byte misaligns the source for `u32`
+        let mut bytes = vec![0u8];
+        bytes.extend_from_slice(vec.as_bytes());
+        let copy = UntypedVec::from_bytes::<u32>(&bytes[1..]);
+        assert_eq!(copy.as_slice::<u32>(), [7, 1, 2, 3]);
+        assert_eq!(copy.clone().as_bytes(), vec.as_bytes());
+    }
+
+    #[cfg(feature = "bytemuck")]
+    #[test]
+    #[should_panic(expected = "wasn't created for plain old data")]
+    fn bytes_of_non_pod() {
+        let vec = UntypedVec::new::<u32>();
+        vec.as_bytes();
+    }
+
+    #[cfg(feature = "bytemuck")]
+    #[test]
+    #[should_panic(expected = "isn't a multiple of the size")]
+    fn from_bytes_partial_element() {
+        UntypedVec::from_bytes::<u64>(&[0; 12]);
+    }
 }
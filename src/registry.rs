@@ -0,0 +1,432 @@
+use std::{any::TypeId, collections::HashMap, fmt, marker::PhantomData};
+
+use serde::{
+    de::{self, DeserializeOwned, DeserializeSeed, MapAccess, SeqAccess, Visitor},
+    ser::{self, SerializeSeq, SerializeStruct},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{ElementDescriptor, UntypedVec};
+
+/// Maps stable type names to the functions needed to (de)serialize [`UntypedVec`]s of that type
+///
+/// Vectors are written as a struct holding the registered name of their element type and a
+/// sequence of their elements:
+///
+/// ```
+/// # use untyped_vec::{TypeRegistry, UntypedVec};
+/// let mut registry = TypeRegistry::new();
+/// registry.register::<u32>("u32");
+///
+/// let mut vec = UntypedVec::new::<u32>();
+/// vec.push(42u32);
+///
+/// let json = serde_json::to_string(&registry.serializable(&vec)).unwrap();
+/// assert_eq!(json, r#"{"type":"u32","elements":[42]}"#);
+///
+/// use serde::de::DeserializeSeed;
+/// let mut deserializer = serde_json::Deserializer::from_str(&json);
+/// let vec = registry.deserialize_seed().deserialize(&mut deserializer).unwrap();
+/// assert_eq!(vec.get::<u32>(0), &42);
+/// ```
+#[derive(Default)]
+pub struct TypeRegistry {
+    types: HashMap<&'static str, Registration>,
+    names: HashMap<TypeId, &'static str>,
+}
+
+struct Registration {
+    desc: ElementDescriptor,
+    serialize: unsafe fn(*const u8) -> *const dyn erased_serde::Serialize,
+    deserialize: DeserializeFn,
+}
+
+type DeserializeFn = fn(
+    ElementDescriptor,
+    &mut dyn erased_serde::Deserializer,
+) -> Result<UntypedVec, erased_serde::Error>;
+
+impl TypeRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `T` under `name`, which has to stay the same across processes and versions
+    ///
+    /// # Panics
+    /// Panics if `name` or `T` is already registered
+    #[track_caller]
+    pub fn register<T>(&mut self, name: &'static str)
+    where
+        T: Serialize + DeserializeOwned + 'static,
+    {
+        self.register_with_descriptor::<T>(name, ElementDescriptor::of::<T>())
+    }
+
+    /// Registers `T` under `name`, recreating deserialized vectors from `desc`.
+    /// This keeps functions like clone or debug captured by `desc` across a round trip
+    ///
+    /// # Panics
+    /// Panics if `desc` doesn't describe `T`, or `name` or `T` is already registered
+    #[track_caller]
+    pub fn register_with_descriptor<T>(&mut self, name: &'static str, desc: ElementDescriptor)
+    where
+        T: Serialize + DeserializeOwned + 'static,
+    {
+        assert!(
+            desc.is::<T>(),
+            "Type mismatch: descriptor describes `{}` but was given `{}`",
+            desc.type_name(),
+            std::any::type_name::<T>()
+        );
+        assert!(
+            !self.types.contains_key(name) && !self.names.contains_key(&TypeId::of::<T>()),
+            "`{}` is already registered",
+            name
+        );
+
+        self.types.insert(
+            name,
+            Registration {
+                desc,
+                serialize: serialize_ptr::<T>,
+                deserialize: deserialize_elements::<T>,
+            },
+        );
+        self.names.insert(TypeId::of::<T>(), name);
+    }
+
+    /// Returns the name `T` is registered under
+    pub fn name_of<T: 'static>(&self) -> Option<&'static str> {
+        self.names.get(&TypeId::of::<T>()).copied()
+    }
+
+    /// Wraps `vec` so it can be passed to any [`Serializer`]. Serialization fails if the element
+    /// type of `vec` isn't registered
+    pub fn serializable<'a>(&'a self, vec: &'a UntypedVec) -> SerializableVec<'a> {
+        SerializableVec {
+            registry: self,
+            vec,
+        }
+    }
+
+    /// Returns a [`DeserializeSeed`] that reads back vectors written through [`TypeRegistry::serializable`]
+    pub fn deserialize_seed(&self) -> VecSeed<'_> {
+        VecSeed { registry: self }
+    }
+}
+
+/// The most memory to reserve up front when deserializing elements
+const MAX_PREALLOCATION: usize = 1024 * 1024;
+
+unsafe fn serialize_ptr<T: Serialize + 'static>(
+    ptr: *const u8,
+) -> *const dyn erased_serde::Serialize {
+    ptr.cast::<T>() as *const dyn erased_serde::Serialize
+}
+
+fn deserialize_elements<T: DeserializeOwned + 'static>(
+    desc: ElementDescriptor,
+    deserializer: &mut dyn erased_serde::Deserializer,
+) -> Result<UntypedVec, erased_serde::Error> {
+    struct ElementsVisitor<T> {
+        desc: ElementDescriptor,
+        _marker: PhantomData<T>,
+    }
+
+    impl<'de, T: DeserializeOwned + 'static> Visitor<'de> for ElementsVisitor<T> {
+        type Value = UntypedVec;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a sequence of `{}`", self.desc.type_name())
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<UntypedVec, A::Error> {
+            let mut vec = UntypedVec::from_descriptor(self.desc);
+            // The size hint comes from the input, so only trust it up to a point, like serde's `Vec` does
+            let size = self.desc.layout().size().max(1);
+            vec.reserve_exact(seq.size_hint().unwrap_or(0).min(MAX_PREALLOCATION / size));
+            while let Some(elem) = seq.next_element::<T>()? {
+                vec.push(elem);
+            }
+            Ok(vec)
+        }
+    }
+
+    deserializer.deserialize_seq(ElementsVisitor::<T> {
+        desc,
+        _marker: PhantomData,
+    })
+}
+
+/// An [`UntypedVec`] paired with the [`TypeRegistry`] used to serialize its elements
+///
+/// Created by [`TypeRegistry::serializable`]
+pub struct SerializableVec<'a> {
+    registry: &'a TypeRegistry,
+    vec: &'a UntypedVec,
+}
+
+impl Serialize for SerializableVec<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let desc = self.vec.descriptor();
+        let name = desc
+            .type_id()
+            .and_then(|type_id| self.registry.names.get(&type_id))
+            .ok_or_else(|| {
+                ser::Error::custom(format_args!("`{}` is not registered", desc.type_name()))
+            })?;
+        let registration = &self.registry.types[name];
+
+        let mut state = serializer.serialize_struct("UntypedVec", 2)?;
+        state.serialize_field("type", name)?;
+        state.serialize_field(
+            "elements",
+            &Elements {
+                vec: self.vec,
+                serialize: registration.serialize,
+            },
+        )?;
+        state.end()
+    }
+}
+
+struct Elements<'a> {
+    vec: &'a UntypedVec,
+    serialize: unsafe fn(*const u8) -> *const dyn erased_serde::Serialize,
+}
+
+impl Serialize for Elements<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.vec.len()))?;
+        for i in 0..self.vec.len() {
+            // SAFETY: The registration was looked up by the TypeId of the vector
+            let elem = unsafe { &*(self.serialize)(self.vec.get_raw(i)) };
+            seq.serialize_element(elem)?;
+        }
+        seq.end()
+    }
+}
+
+/// Deserializes an [`UntypedVec`] of any type registered in a [`TypeRegistry`]
+///
+/// Created by [`TypeRegistry::deserialize_seed`]
+pub struct VecSeed<'a> {
+    registry: &'a TypeRegistry,
+}
+
+impl<'de> DeserializeSeed<'de> for VecSeed<'_> {
+    type Value = UntypedVec;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<UntypedVec, D::Error> {
+        deserializer.deserialize_struct("UntypedVec", &["type", "elements"], self)
+    }
+}
+
+impl<'de> Visitor<'de> for VecSeed<'_> {
+    type Value = UntypedVec;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a struct UntypedVec")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<UntypedVec, A::Error> {
+        let registration = seq
+            .next_element_seed(NameSeed(self.registry))?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        seq.next_element_seed(ElementsSeed(registration))?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<UntypedVec, A::Error> {
+        // The elements can only be read once their type is known
+        match map.next_key::<String>()?.as_deref() {
+            Some("type") => {}
+            _ => return Err(de::Error::missing_field("type")),
+        }
+        let registration = map.next_value_seed(NameSeed(self.registry))?;
+
+        match map.next_key::<String>()?.as_deref() {
+            Some("elements") => {}
+            _ => return Err(de::Error::missing_field("elements")),
+        }
+        map.next_value_seed(ElementsSeed(registration))
+    }
+}
+
+/// Reads a type name and looks up its registration
+struct NameSeed<'a>(&'a TypeRegistry);
+
+impl<'de, 'a> DeserializeSeed<'de> for NameSeed<'a> {
+    type Value = &'a Registration;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<&'a Registration, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        self.0
+            .types
+            .get(name.as_str())
+            .ok_or_else(|| de::Error::custom(format_args!("`{}` is not registered", name)))
+    }
+}
+
+struct ElementsSeed<'a>(&'a Registration);
+
+impl<'de> DeserializeSeed<'de> for ElementsSeed<'_> {
+    type Value = UntypedVec;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<UntypedVec, D::Error> {
+        let mut erased = <dyn erased_serde::Deserializer>::erase(deserializer);
+        (self.0.deserialize)(self.0.desc, &mut erased).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::de::DeserializeSeed;
+
+    use crate::{ElementDescriptor, TypeRegistry, UntypedVec};
+
+    fn registry() -> TypeRegistry {
+        let mut registry = TypeRegistry::new();
+        registry.register::<u64>("u64");
+        registry.register_with_descriptor::<String>(
+            "string",
+            ElementDescriptor::of::<String>().with_clone::<String>(),
+        );
+        registry.register::<(u8, Option<String>)>("pair");
+        registry
+    }
+
+    fn round_trip(registry: &TypeRegistry, vec: &UntypedVec) -> UntypedVec {
+        let json = serde_json::to_string(&registry.serializable(vec)).unwrap();
+        let mut deserializer = serde_json::Deserializer::from_str(&json);
+        registry
+            .deserialize_seed()
+            .deserialize(&mut deserializer)
+            .unwrap()
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let registry = registry();
+
+        let mut strings = UntypedVec::new::<String>();
+        let mut pairs = UntypedVec::new::<(u8, Option<String>)>();
+        for i in 0..10u8 {
+            strings.push(i.to_string());
+            pairs.push((i, (i % 2 == 0).then(|| i.to_string())));
+        }
+
+        let strings = round_trip(&registry, &strings);
+        assert_eq!(strings.len(), 10);
+        assert_eq!(strings.get::<String>(3), "3");
+        // The registered descriptor is used to recreate the vector
+        assert!(strings.try_clone().is_some());
+
+        let pairs = round_trip(&registry, &pairs);
+        assert_eq!(
+            pairs.get::<(u8, Option<String>)>(4),
+            &(4, Some(String::from("4")))
+        );
+        assert_eq!(pairs.get::<(u8, Option<String>)>(5), &(5, None));
+
+        let empty = round_trip(&registry, &UntypedVec::new::<u64>());
+        assert!(empty.is_empty());
+        assert!(empty.is::<u64>());
+        assert_eq!(registry.name_of::<u64>(), Some("u64"));
+    }
+
+    #[test]
+    fn json_format() {
+        let registry = registry();
+        let mut vec = UntypedVec::new::<u64>();
+        vec.push(1u64);
+        vec.push(2u64);
+
+        let json = serde_json::to_value(registry.serializable(&vec)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "type": "u64", "elements": [1, 2] })
+        );
+
+        // Sequences are accepted as well, for formats without field names
+        let json = r#"["u64", [3, 4]]"#;
+        let mut deserializer = serde_json::Deserializer::from_str(json);
+        let vec = registry
+            .deserialize_seed()
+            .deserialize(&mut deserializer)
+            .unwrap();
+        assert_eq!(vec.as_slice::<u64>(), [3, 4]);
+    }
+
+    #[test]
+    fn unregistered_types() {
+        let registry = registry();
+        let vec = UntypedVec::new::<u32>();
+        let err = serde_json::to_string(&registry.serializable(&vec)).unwrap_err();
+        assert!(err.to_string().contains("`u32` is not registered"));
+
+        let json = r#"{"type":"u32","elements":[]}"#;
+        let mut deserializer = serde_json::Deserializer::from_str(json);
+        let err = registry
+            .deserialize_seed()
+            .deserialize(&mut deserializer)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("`u32` is not registered"));
+
+        let json = r#"{"elements":[],"type":"u64"}"#;
+        let mut deserializer = serde_json::Deserializer::from_str(json);
+        assert!(registry
+            .deserialize_seed()
+            .deserialize(&mut deserializer)
+            .is_err());
+    }
+
+    #[test]
+    fn mismatched_elements() {
+        let registry = registry();
+        let json = r#"{"type":"u64","elements":["foo"]}"#;
+        let mut deserializer = serde_json::Deserializer::from_str(json);
+        assert!(registry
+            .deserialize_seed()
+            .deserialize(&mut deserializer)
+            .is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "`u64` is already registered")]
+    fn register_twice() {
+        let mut registry = registry();
+        registry.register::<u64>("u64");
+    }
+
+    #[test]
+    fn untrusted_size_hint() {
+        // Claims to hold far more elements than could ever be allocated
+        struct Lying(std::vec::IntoIter<u64>);
+        impl Iterator for Lying {
+            type Item = u64;
+            fn next(&mut self) -> Option<u64> {
+                self.0.next()
+            }
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                (usize::MAX, Some(usize::MAX))
+            }
+        }
+
+        let deserializer = serde::de::value::SeqDeserializer::<_, serde::de::value::Error>::new(
+            Lying(vec![1, 2, 3].into_iter()),
+        );
+        let vec = super::deserialize_elements::<u64>(
+            ElementDescriptor::of::<u64>(),
+            &mut <dyn erased_serde::Deserializer>::erase(deserializer),
+        )
+        .unwrap();
+        assert_eq!(vec.as_slice::<u64>(), [1, 2, 3]);
+        assert!(vec.capacity() <= super::MAX_PREALLOCATION / 8);
+    }
+}
@@ -0,0 +1,1161 @@
+use std::{
+    alloc::Layout,
+    fmt,
+    hash::{Hash, Hasher},
+    mem::{ManuallyDrop, MaybeUninit},
+    ops::{Range, RangeBounds},
+    ptr::NonNull,
+};
+
+use crate::{
+    iter::{self, sealed},
+    utils, Drain, ElementDescriptor, IntoIter, TryReserveError, UntypedVec, UntypedVecError,
+};
+
+/// The alignment of the inline buffer. Elements with a larger alignment always live on the heap
+const INLINE_ALIGN: usize = 16;
+
+/// Holds the elements inline, or points to the heap buffer once spilled
+#[repr(C, align(16))]
+union Data<const N: usize> {
+    inline: [MaybeUninit<u8>; N],
+    heap: NonNull<u8>,
+}
+
+/// An [`UntypedVec`] that stores its elements inline in `N_BYTES` bytes until they no longer fit,
+/// after which they are moved to the heap
+///
+/// Elements are stored inline as long as `len * size` fits in `N_BYTES` and their alignment is at
+/// most 16. Once spilled the elements stay on the heap, until [`UntypedSmallVec::shrink_to_fit`]
+/// finds that they fit inline again
+pub struct UntypedSmallVec<const N_BYTES: usize> {
+    data: Data<N_BYTES>,
+    len: usize,
+    // The capacity of the heap buffer, `None` while the elements are inline
+    heap_capacity: Option<usize>,
+    desc: ElementDescriptor,
+}
+
+impl<const N_BYTES: usize> UntypedSmallVec<N_BYTES> {
+    pub fn new<T: 'static>() -> Self {
+        Self::from_descriptor(ElementDescriptor::of::<T>())
+    }
+
+    /// Creates an empty vector that can be cloned with [`UntypedSmallVec::try_clone`] or [`Clone`]
+    pub fn new_cloneable<T: Clone + 'static>() -> Self {
+        Self::from_descriptor(ElementDescriptor::of::<T>().with_clone::<T>())
+    }
+
+    /// Creates an empty vector whose elements are printed by its [`Debug`](fmt::Debug) implementation
+    pub fn new_debug<T: fmt::Debug + 'static>() -> Self {
+        Self::from_descriptor(ElementDescriptor::of::<T>().with_debug::<T>())
+    }
+
+    /// Creates an empty vector for elements described by `desc`
+    pub fn from_descriptor(desc: ElementDescriptor) -> Self {
+        Self {
+            data: Data {
+                inline: [MaybeUninit::uninit(); N_BYTES],
+            },
+            len: 0,
+            heap_capacity: None,
+            desc,
+        }
+    }
+
+    pub fn with_capacity<T: 'static>(capacity: usize) -> Self {
+        let mut vec = Self::new::<T>();
+        vec.reserve_exact(capacity);
+        vec
+    }
+
+    /// Clones the vector element by element, if its descriptor has a clone function or is marked as [`Copy`].
+    /// The clone keeps its elements inline if they fit
+    pub fn try_clone(&self) -> Option<Self> {
+        self.try_clone_elements(0..self.len)
+    }
+
+    /// Returns the name of the element type, as given by [`std::any::type_name`]
+    pub fn type_name(&self) -> &'static str {
+        self.desc.type_name
+    }
+    /// Returns whether the elements of this vector are of type `T`
+    pub fn is<T: 'static>(&self) -> bool {
+        self.desc.is::<T>()
+    }
+    /// Returns the descriptor of the element type
+    pub fn descriptor(&self) -> &ElementDescriptor {
+        &self.desc
+    }
+
+    /// Returns whether the elements have been moved to the heap
+    pub fn spilled(&self) -> bool {
+        self.heap_capacity.is_some()
+    }
+    /// Returns the number of elements that fit in the inline buffer
+    pub fn inline_capacity(&self) -> usize {
+        let layout = self.desc.layout;
+        if layout.size() == 0 {
+            usize::MAX
+        } else if layout.align() > INLINE_ALIGN {
+            0
+        } else {
+            N_BYTES / layout.size()
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.heap_capacity.unwrap_or_else(|| self.inline_capacity())
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Reserves capacity for at least `additional` more elements, spilling to the heap if
+    /// they don't fit inline
+    #[track_caller]
+    pub fn reserve(&mut self, additional: usize) {
+        utils::handle_reserve(self.try_reserve(additional))
+    }
+    /// Like [`UntypedSmallVec::reserve`], but returns an error instead of panicking or aborting
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        if self.spilled() {
+            return self.with_heap(|heap| heap.try_reserve(additional));
+        }
+
+        let required = self
+            .len
+            .checked_add(additional)
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        if required > self.inline_capacity() {
+            self.spill(required, false)?;
+        }
+        Ok(())
+    }
+
+    /// Reserves capacity for exactly `additional` more elements, spilling to the heap if
+    /// they don't fit inline
+    #[track_caller]
+    pub fn reserve_exact(&mut self, additional: usize) {
+        utils::handle_reserve(self.try_reserve_exact(additional))
+    }
+    /// Like [`UntypedSmallVec::reserve_exact`], but returns an error instead of panicking or aborting
+    pub fn try_reserve_exact(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        if self.spilled() {
+            return self.with_heap(|heap| heap.try_reserve_exact(additional));
+        }
+
+        let required = self
+            .len
+            .checked_add(additional)
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        if required > self.inline_capacity() {
+            self.spill(required, true)?;
+        }
+        Ok(())
+    }
+
+    /// Moves spilled elements back inline if they fit, and shrinks the heap buffer as much
+    /// as possible otherwise
+    pub fn shrink_to_fit(&mut self) {
+        self.shrink_to(0)
+    }
+
+    /// Shrinks the capacity of the vector to the greater of `min_capacity` and its length, moving
+    /// spilled elements back inline if that fits. Does nothing if the capacity is already lower
+    pub fn shrink_to(&mut self, min_capacity: usize) {
+        let Some(capacity) = self.heap_capacity else {
+            return;
+        };
+        let new_capacity = self.len.max(min_capacity);
+        if new_capacity > self.inline_capacity() {
+            return self.with_heap(|heap| heap.shrink_to(new_capacity));
+        }
+
+        unsafe {
+            // The elements move inline, so dropping the heap vector only frees its buffer
+            let heap = UntypedVec::from_raw_parts(self.data.heap, 0, capacity, self.desc);
+            self.heap_capacity = None;
+            std::ptr::copy_nonoverlapping(
+                heap.ptr().as_ptr(),
+                self.base_mut(),
+                self.len * self.desc.layout.size(),
+            );
+        }
+    }
+
+    /// Converts the vector into an [`UntypedVec`], moving the elements to the heap if needed
+    pub fn into_vec(mut self) -> UntypedVec {
+        if !self.spilled() {
+            utils::handle_reserve(self.spill(self.len, true));
+        }
+        let vec = ManuallyDrop::new(self);
+        // SAFETY: The heap buffer was created by `spill`, and ownership of it moves out of `vec`
+        unsafe {
+            UntypedVec::from_raw_parts(vec.data.heap, vec.len, vec.heap_capacity.unwrap(), vec.desc)
+        }
+    }
+
+    #[track_caller]
+    pub fn push<T: 'static>(&mut self, elem: T) {
+        self.try_push(elem)
+            .unwrap_or_else(|(_, err)| panic!("{}", err))
+    }
+    /// Pushes `elem`, handing it back along with the error if the type doesn't match or
+    /// the vector can't grow
+    pub fn try_push<T: 'static>(&mut self, elem: T) -> Result<(), (T, UntypedVecError)> {
+        if let Err(err) = self.check_type::<T>() {
+            return Err((elem, err));
+        }
+        if let Err(err) = self.try_reserve(1) {
+            return Err((elem, err.into()));
+        }
+
+        unsafe { std::ptr::write(self.ptr_to_mut(self.len).cast::<T>(), elem) };
+        self.len += 1;
+        Ok(())
+    }
+
+    #[track_caller]
+    pub fn pop<T: 'static>(&mut self) -> Option<T> {
+        self.try_pop().unwrap_or_else(|err| panic!("{}", err))
+    }
+    pub fn try_pop<T: 'static>(&mut self) -> Result<Option<T>, UntypedVecError> {
+        self.check_type::<T>()?;
+        if self.is_empty() {
+            return Ok(None);
+        }
+
+        self.len -= 1;
+        Ok(Some(unsafe {
+            std::ptr::read(self.ptr_to(self.len).cast::<T>())
+        }))
+    }
+
+    #[track_caller]
+    pub fn get<T: 'static>(&self, index: usize) -> &T {
+        self.try_get(index).unwrap_or_else(|err| panic!("{}", err))
+    }
+    pub fn try_get<T: 'static>(&self, index: usize) -> Result<&T, UntypedVecError> {
+        self.check_type::<T>()?;
+        self.check_index(index)?;
+        Ok(unsafe { &*self.ptr_to(index).cast::<T>() })
+    }
+
+    #[track_caller]
+    pub fn get_mut<T: 'static>(&mut self, index: usize) -> &mut T {
+        self.try_get_mut(index)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+    pub fn try_get_mut<T: 'static>(&mut self, index: usize) -> Result<&mut T, UntypedVecError> {
+        self.check_type::<T>()?;
+        self.check_index(index)?;
+        Ok(unsafe { &mut *self.ptr_to_mut(index).cast::<T>() })
+    }
+
+    /// Removes the element at `index`, replacing it with the last element
+    #[track_caller]
+    pub fn swap_remove<T: 'static>(&mut self, index: usize) -> T {
+        self.try_swap_remove(index)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+    pub fn try_swap_remove<T: 'static>(&mut self, index: usize) -> Result<T, UntypedVecError> {
+        self.check_type::<T>()?;
+        self.check_index(index)?;
+
+        let mut value = MaybeUninit::<T>::uninit();
+        unsafe {
+            self.swap_remove_raw(index, value.as_mut_ptr().cast::<u8>());
+            Ok(value.assume_init())
+        }
+    }
+
+    /// Removes the element at `index` and drops it, replacing it with the last element.
+    /// Unlike [`UntypedSmallVec::swap_remove`], this doesn't need to know the element type
+    #[track_caller]
+    pub fn swap_remove_drop(&mut self, index: usize) {
+        self.check_index(index)
+            .unwrap_or_else(|err| panic!("{}", err));
+
+        let last = self.len() - 1;
+        if index != last {
+            unsafe {
+                let base = self.base_mut();
+                let size = self.desc.layout.size();
+                std::ptr::swap_nonoverlapping(base.add(index * size), base.add(last * size), size)
+            };
+        }
+        self.truncate(last);
+    }
+
+    /// Inserts `elem` at `index`, shifting all elements after it to the right
+    #[track_caller]
+    pub fn insert<T: 'static>(&mut self, index: usize, elem: T) {
+        self.check_type::<T>()
+            .unwrap_or_else(|err| panic!("{}", err));
+        assert!(
+            index <= self.len(),
+            "Insertion index (is {}) should be <= len (is {})",
+            index,
+            self.len()
+        );
+
+        self.reserve(1);
+        let size = self.desc.layout.size();
+        unsafe {
+            let tail = self.len() - index;
+            let ptr = self.ptr_to_mut(index);
+            std::ptr::copy(ptr, ptr.add(size), tail * size);
+            std::ptr::write(ptr.cast::<T>(), elem);
+        }
+        self.len += 1;
+    }
+
+    /// Removes and returns the element at `index`, shifting all elements after it to the left
+    #[track_caller]
+    pub fn remove<T: 'static>(&mut self, index: usize) -> T {
+        self.check_type::<T>()
+            .and_then(|_| self.check_index(index))
+            .unwrap_or_else(|err| panic!("{}", err));
+
+        let size = self.desc.layout.size();
+        unsafe {
+            let tail = self.len() - index - 1;
+            let ptr = self.ptr_to_mut(index);
+            let value = std::ptr::read(ptr.cast::<T>());
+            std::ptr::copy(ptr.add(size), ptr, tail * size);
+            self.len -= 1;
+            value
+        }
+    }
+
+    /// Shortens the vector to `len` elements, dropping the rest.
+    /// Does nothing if `len` is greater than the current length
+    pub fn truncate(&mut self, len: usize) {
+        let desc = self.desc;
+        let (base, old_len) = self.raw_parts_mut();
+        // SAFETY: The first `len()` elements are initialized
+        unsafe { utils::truncate(&desc, base, old_len, len) }
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0)
+    }
+
+    /// Retains only the elements for which `f` returns true, preserving their order
+    #[track_caller]
+    pub fn retain<T: 'static, F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.retain_mut(|elem: &mut T| f(elem))
+    }
+
+    /// Retains only the elements for which `f` returns true, preserving their order
+    #[track_caller]
+    pub fn retain_mut<T: 'static, F>(&mut self, f: F)
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        self.check_type::<T>()
+            .unwrap_or_else(|err| panic!("{}", err));
+
+        let (base, len) = self.raw_parts_mut();
+        // SAFETY: The elements are of type `T`, and the first `len()` are initialized
+        unsafe { utils::retain_mut(base.cast::<T>(), len, f) }
+    }
+
+    /// Removes all but the first of consecutive elements for which `same_bucket` returns true.
+    /// `same_bucket` is passed the element in question and the last element that was kept
+    #[track_caller]
+    pub fn dedup_by<T: 'static, F>(&mut self, same_bucket: F)
+    where
+        F: FnMut(&mut T, &mut T) -> bool,
+    {
+        self.check_type::<T>()
+            .unwrap_or_else(|err| panic!("{}", err));
+
+        let (base, len) = self.raw_parts_mut();
+        // SAFETY: As in `retain_mut`
+        unsafe { utils::dedup_by(base.cast::<T>(), len, same_bucket) }
+    }
+
+    /// Returns whether the vector contains an element equal to the one behind `elem`
+    ///
+    /// # Panics
+    /// Panics if the descriptor has no eq function
+    ///
+    /// # Safety
+    /// `elem` must point to a valid value of the element type
+    #[track_caller]
+    pub unsafe fn contains_raw(&self, elem: *const u8) -> bool {
+        self.position_raw(elem).is_some()
+    }
+
+    /// Returns the index of the first element equal to the one behind `elem`
+    ///
+    /// # Panics
+    /// Panics if the descriptor has no eq function
+    ///
+    /// # Safety
+    /// `elem` must point to a valid value of the element type
+    #[track_caller]
+    pub unsafe fn position_raw(&self, elem: *const u8) -> Option<usize> {
+        utils::position(&self.desc, self.base(), self.len, elem)
+    }
+
+    /// Sorts the vector with the descriptor's cmp function, preserving the order of equal elements
+    ///
+    /// # Panics
+    /// Panics if the descriptor has no cmp function
+    #[track_caller]
+    pub fn sort(&mut self) {
+        let permutation =
+            unsafe { utils::sort_permutation(&self.desc, self.base(), self.len, true) };
+        let base = self.base_mut();
+        unsafe { utils::permute(&self.desc, base, &permutation) }
+    }
+
+    /// Sorts the vector with the descriptor's cmp function, without preserving the order of equal elements
+    ///
+    /// # Panics
+    /// Panics if the descriptor has no cmp function
+    #[track_caller]
+    pub fn sort_unstable(&mut self) {
+        let permutation =
+            unsafe { utils::sort_permutation(&self.desc, self.base(), self.len, false) };
+        let base = self.base_mut();
+        unsafe { utils::permute(&self.desc, base, &permutation) }
+    }
+
+    /// Returns the permutation that stably sorts the vector by the key `f` extracts from each element,
+    /// see [`UntypedVec::sort_by_key_raw`]
+    pub fn sort_by_key_raw<K, F>(&self, f: F) -> Vec<usize>
+    where
+        K: Ord,
+        F: FnMut(*const u8) -> K,
+    {
+        unsafe { utils::sort_by_key(&self.desc, self.base(), self.len, f) }
+    }
+
+    /// Binary searches a sorted vector for the element behind `elem`, like [`slice::binary_search`]
+    ///
+    /// # Panics
+    /// Panics if the descriptor has no cmp function
+    ///
+    /// # Safety
+    /// `elem` must point to a valid value of the element type
+    #[track_caller]
+    pub unsafe fn binary_search_raw(&self, elem: *const u8) -> Result<usize, usize> {
+        utils::binary_search(&self.desc, self.base(), self.len, elem)
+    }
+
+    /// Reorders the elements so that the element at index `permutation[i]` ends up at index `i`,
+    /// see [`UntypedVec::apply_permutation`]
+    ///
+    /// # Panics
+    /// Panics if `permutation` is not a permutation of `0..self.len()`
+    #[track_caller]
+    pub fn apply_permutation(&mut self, permutation: &[usize]) {
+        utils::check_permutation(permutation, self.len);
+        let base = self.base_mut();
+        unsafe { utils::permute(&self.desc, base, permutation) }
+    }
+
+    /// Returns a new vector holding copies of the elements at `indices`, in that order
+    ///
+    /// # Panics
+    /// Panics if an index is out of bounds, or the descriptor has no clone function
+    /// and isn't marked as [`Copy`]
+    #[track_caller]
+    pub fn gather(&self, indices: &[usize]) -> Self {
+        self.try_clone_elements(indices.iter().copied())
+            .unwrap_or_else(|| {
+                panic!(
+                    "Vector of `{}` was created without a clone function",
+                    self.desc.type_name
+                )
+            })
+    }
+
+    /// Moves the elements of `src` into this vector, so that element `i` of `src` replaces
+    /// the element at `indices[i]`. Replaced elements are dropped
+    ///
+    /// # Panics
+    /// Panics if `src` stores a different type, its len doesn't match the number of indices,
+    /// or an index is out of bounds
+    #[track_caller]
+    pub fn scatter<const M: usize>(&mut self, indices: &[usize], mut src: UntypedSmallVec<M>) {
+        utils::check_scatter(&self.desc, self.len, indices, &src.desc, src.len);
+
+        // Ownership moves out of `src` up front, if a destructor panics the rest are leaked
+        src.len = 0;
+        let base = self.base_mut();
+        unsafe { utils::scatter(&self.desc, base, indices, src.base()) }
+    }
+
+    /// Removes the elements in `range`, returning them through an iterator.
+    /// Elements that are not consumed are dropped, and the tail is shifted back when the iterator is dropped
+    #[track_caller]
+    pub fn drain<T: 'static, R>(&mut self, range: R) -> Drain<'_, T>
+    where
+        R: RangeBounds<usize>,
+    {
+        self.check_type::<T>()
+            .unwrap_or_else(|err| panic!("{}", err));
+        let Range { start, end } = utils::slice_range(range, self.len());
+        let (base, len) = self.raw_parts_mut();
+        // SAFETY: The elements are of type `T`, and the range was checked against the len
+        unsafe { Drain::new(base.cast::<T>(), len, start, end) }
+    }
+
+    /// Returns an iterator that moves the elements out of the vector, without moving inline
+    /// elements to the heap. Elements that are not consumed are dropped along with the iterator
+    ///
+    /// [`IntoIterator`] can't be implemented, as the element type is only known by the caller
+    #[allow(clippy::should_implement_trait)]
+    #[track_caller]
+    pub fn into_iter<T: 'static>(self) -> IntoIter<T, Self> {
+        self.check_type::<T>()
+            .unwrap_or_else(|err| panic!("{}", err));
+        // SAFETY: The type was checked above
+        unsafe { IntoIter::new(self) }
+    }
+
+    #[track_caller]
+    pub fn as_slice<T: 'static>(&self) -> &[T] {
+        self.check_type::<T>()
+            .unwrap_or_else(|err| panic!("{}", err));
+
+        // SAFETY: The buffer is always aligned for `T`, and the first `len` elements are initialized
+        unsafe { std::slice::from_raw_parts(self.base().cast::<T>(), self.len()) }
+    }
+    #[track_caller]
+    pub fn as_mut_slice<T: 'static>(&mut self) -> &mut [T] {
+        self.check_type::<T>()
+            .unwrap_or_else(|err| panic!("{}", err));
+
+        let len = self.len();
+        // SAFETY: As above
+        unsafe { std::slice::from_raw_parts_mut(self.base_mut().cast::<T>(), len) }
+    }
+
+    #[track_caller]
+    pub fn iter<T: 'static>(&self) -> std::slice::Iter<'_, T> {
+        self.as_slice().iter()
+    }
+    #[track_caller]
+    pub fn iter_mut<T: 'static>(&mut self) -> std::slice::IterMut<'_, T> {
+        self.as_mut_slice().iter_mut()
+    }
+
+    /// Returns the layout of a single element
+    pub fn element_layout(&self) -> Layout {
+        self.desc.layout()
+    }
+
+    /// Moves an element into the vector by copying `element_layout().size()` bytes from `src`
+    ///
+    /// # Safety
+    /// See [`UntypedVec::push_raw`]
+    #[track_caller]
+    pub unsafe fn push_raw(&mut self, src: *const u8) {
+        self.reserve(1);
+        std::ptr::copy_nonoverlapping(src, self.ptr_to_mut(self.len), self.desc.layout.size());
+        self.len += 1;
+    }
+
+    /// Returns a pointer to the element at `index`.
+    /// The pointer is invalidated by any operation that moves elements or the vector itself
+    #[track_caller]
+    pub fn get_raw(&self, index: usize) -> *const u8 {
+        self.check_index(index)
+            .unwrap_or_else(|err| panic!("{}", err));
+        unsafe { self.ptr_to(index) }
+    }
+    /// Returns a mutable pointer to the element at `index`.
+    /// The pointer is invalidated by any operation that moves elements or the vector itself
+    #[track_caller]
+    pub fn get_raw_mut(&mut self, index: usize) -> *mut u8 {
+        self.check_index(index)
+            .unwrap_or_else(|err| panic!("{}", err));
+        unsafe { self.ptr_to_mut(index) }
+    }
+
+    /// Moves the element at `index` into `dst`, replacing it with the last element
+    ///
+    /// # Safety
+    /// See [`UntypedVec::swap_remove_raw`]
+    #[track_caller]
+    pub unsafe fn swap_remove_raw(&mut self, index: usize, dst: *mut u8) {
+        self.check_index(index)
+            .unwrap_or_else(|err| panic!("{}", err));
+
+        let size = self.desc.layout.size();
+        let last = self.len() - 1;
+        let base = self.base_mut();
+        std::ptr::copy_nonoverlapping(base.add(index * size), dst, size);
+        std::ptr::copy(base.add(last * size), base.add(index * size), size);
+        self.len -= 1;
+    }
+
+    /// Moves the last element into `dst`, returning false if the vector is empty
+    ///
+    /// # Safety
+    /// See [`UntypedVec::pop_raw`]
+    pub unsafe fn pop_raw(&mut self, dst: *mut u8) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+
+        self.len -= 1;
+        std::ptr::copy_nonoverlapping(self.ptr_to(self.len), dst, self.desc.layout.size());
+        true
+    }
+
+    /// Moves the inline elements to a heap buffer with room for at least `capacity` elements,
+    /// or exactly `capacity` if `exact` is set
+    fn spill(&mut self, capacity: usize, exact: bool) -> Result<(), TryReserveError> {
+        debug_assert!(!self.spilled());
+
+        let mut heap = UntypedVec::from_descriptor(self.desc);
+        if exact {
+            heap.try_reserve_exact(capacity)?;
+        } else {
+            heap.try_reserve(capacity)?;
+        }
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                self.base(),
+                heap.ptr().as_ptr(),
+                self.len * self.desc.layout.size(),
+            );
+        }
+        let (ptr, _, capacity) = heap.into_raw_parts();
+        self.data.heap = ptr;
+        self.heap_capacity = Some(capacity);
+        Ok(())
+    }
+
+    /// Duplicates the elements at `indices` into a new vector, or returns `None` if the
+    /// descriptor can't clone them
+    #[track_caller]
+    fn try_clone_elements<I>(&self, indices: I) -> Option<Self>
+    where
+        I: ExactSizeIterator<Item = usize>,
+    {
+        if !self.desc.is_cloneable() {
+            return None;
+        }
+
+        let mut vec = Self::from_descriptor(self.desc);
+        vec.reserve_exact(indices.len());
+        let (dst, dst_len) = vec.raw_parts_mut();
+        unsafe { utils::clone_elements(&self.desc, self.base(), self.len, indices, dst, dst_len) };
+        Some(vec)
+    }
+
+    /// Lends the heap buffer out as an [`UntypedVec`], so it grows and shrinks the same way.
+    /// The vector must be spilled, and `f` must not panic after changing the buffer
+    fn with_heap<R>(&mut self, f: impl FnOnce(&mut UntypedVec) -> R) -> R {
+        let capacity = self.heap_capacity.expect("The vector isn't spilled");
+        // If `f` panics the buffer is still owned by `self`, so it must not be freed here
+        let mut heap = ManuallyDrop::new(unsafe {
+            UntypedVec::from_raw_parts(self.data.heap, self.len, capacity, self.desc)
+        });
+        let result = f(&mut heap);
+
+        let (ptr, len, capacity) = ManuallyDrop::into_inner(heap).into_raw_parts();
+        self.data.heap = ptr;
+        self.len = len;
+        self.heap_capacity = Some(capacity);
+        result
+    }
+
+    fn check_type<T: 'static>(&self) -> Result<(), UntypedVecError> {
+        self.desc.check_type::<T>()
+    }
+    fn check_index(&self, index: usize) -> Result<(), UntypedVecError> {
+        utils::check_index(index, self.len())
+    }
+
+    fn base(&self) -> *const u8 {
+        match self.heap_capacity {
+            Some(_) => unsafe { self.data.heap.as_ptr() },
+            // ZSTs may be aligned beyond the inline buffer
+            None if self.desc.layout.size() == 0 => {
+                utils::dangling(self.desc.layout.align()).as_ptr()
+            }
+            None => std::ptr::addr_of!(self.data).cast::<u8>(),
+        }
+    }
+    fn base_mut(&mut self) -> *mut u8 {
+        self.raw_parts_mut().0
+    }
+
+    /// Returns the base pointer together with the len. The fields are borrowed separately, so
+    /// updating the len doesn't invalidate the pointer like a second call through `&mut self` would
+    fn raw_parts_mut(&mut self) -> (*mut u8, &mut usize) {
+        let base = match self.heap_capacity {
+            Some(_) => unsafe { self.data.heap.as_ptr() },
+            None if self.desc.layout.size() == 0 => {
+                utils::dangling(self.desc.layout.align()).as_ptr()
+            }
+            None => std::ptr::addr_of_mut!(self.data).cast::<u8>(),
+        };
+        (base, &mut self.len)
+    }
+
+    /// # Safety
+    /// Index should be less than capacity
+    unsafe fn ptr_to(&self, index: usize) -> *const u8 {
+        debug_assert!(index < self.capacity());
+        self.base().add(index * self.desc.layout.size())
+    }
+    /// # Safety
+    /// Index should be less than capacity
+    unsafe fn ptr_to_mut(&mut self, index: usize) -> *mut u8 {
+        debug_assert!(index < self.capacity());
+        self.base_mut().add(index * self.desc.layout.size())
+    }
+}
+
+impl<const N_BYTES: usize> fmt::Debug for UntypedSmallVec<N_BYTES> {
+    /// Prints the elements like a [`Vec`] would if the descriptor has a debug function,
+    /// and a summary of the vector otherwise
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.desc.debug {
+            Some(debug) => f
+                .debug_list()
+                .entries((0..self.len()).map(|i| utils::DebugElement {
+                    ptr: unsafe { self.ptr_to(i) },
+                    debug,
+                }))
+                .finish(),
+            None => f
+                .debug_struct("UntypedSmallVec")
+                .field("type", &self.desc.type_name)
+                .field("len", &self.len())
+                .field("spilled", &self.spilled())
+                .finish(),
+        }
+    }
+}
+
+impl<const N_BYTES: usize> Clone for UntypedSmallVec<N_BYTES> {
+    /// # Panics
+    /// Panics if the vector was not created with a clone function, see [`UntypedSmallVec::try_clone`]
+    #[track_caller]
+    fn clone(&self) -> Self {
+        self.try_clone().unwrap_or_else(|| {
+            panic!(
+                "Vector of `{}` was created without a clone function",
+                self.desc.type_name
+            )
+        })
+    }
+}
+
+impl<const N_BYTES: usize, const M_BYTES: usize> PartialEq<UntypedSmallVec<M_BYTES>>
+    for UntypedSmallVec<N_BYTES>
+{
+    /// Vectors are equal if they store the same type and their elements are pairwise equal,
+    /// regardless of whether they are stored inline
+    ///
+    /// # Panics
+    /// Panics if the vectors store the same type, but were created without an eq function
+    #[track_caller]
+    fn eq(&self, other: &UntypedSmallVec<M_BYTES>) -> bool {
+        if !self.desc.describes_same_type(&other.desc) {
+            return false;
+        }
+
+        unsafe { utils::elements_eq(&self.desc, self.base(), self.len, other.base(), other.len) }
+    }
+}
+
+impl<const N_BYTES: usize> Eq for UntypedSmallVec<N_BYTES> {}
+
+impl<const N_BYTES: usize> Hash for UntypedSmallVec<N_BYTES> {
+    /// # Panics
+    /// Panics if the vector was created without a hash function
+    #[track_caller]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        unsafe { utils::hash_elements(&self.desc, self.base(), self.len, state) }
+    }
+}
+
+impl<const N_BYTES: usize> iter::Buffer for UntypedSmallVec<N_BYTES> {}
+impl<const N_BYTES: usize> sealed::Buffer for UntypedSmallVec<N_BYTES> {
+    fn base(&self) -> *const u8 {
+        self.base()
+    }
+    fn base_mut(&mut self) -> *mut u8 {
+        self.base_mut()
+    }
+    fn len_mut(&mut self) -> &mut usize {
+        &mut self.len
+    }
+}
+
+impl<const N_BYTES: usize> Drop for UntypedSmallVec<N_BYTES> {
+    fn drop(&mut self) {
+        self.clear();
+        if let Some(capacity) = self.heap_capacity {
+            // The elements are gone, so this only frees the buffer
+            drop(unsafe { UntypedVec::from_raw_parts(self.data.heap, 0, capacity, self.desc) });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::hash_map::DefaultHasher,
+        hash::{Hash, Hasher},
+        mem::ManuallyDrop,
+        rc::Rc,
+    };
+
+    use crate::{utils::to_const_ptr as to_ptr, ElementDescriptor, UntypedSmallVec};
+
+    #[test]
+    fn push_and_spill() {
+        let mut vec = UntypedSmallVec::<32>::new::<u64>();
+        assert_eq!(vec.inline_capacity(), 4);
+        for i in 0..4u64 {
+            vec.push(i);
+        }
+        assert!(!vec.spilled());
+        assert_eq!(vec.as_slice::<u64>(), [0, 1, 2, 3]);
+
+        vec.push(4u64);
+        assert!(vec.spilled());
+        assert_eq!(vec.as_slice::<u64>(), [0, 1, 2, 3, 4]);
+
+        assert_eq!(vec.pop::<u64>(), Some(4));
+        vec.insert(0, 10u64);
+        assert_eq!(vec.remove::<u64>(1), 0);
+        assert_eq!(vec.swap_remove::<u64>(0), 10);
+        assert_eq!(vec.iter::<u64>().copied().collect::<Vec<_>>(), [3, 1, 2]);
+        assert!(vec.spilled());
+
+        let vec = vec.into_vec();
+        assert_eq!(vec.as_slice::<u64>(), [3, 1, 2]);
+    }
+
+    #[test]
+    fn inline_operations() {
+        let mut vec = UntypedSmallVec::<96>::new::<String>();
+        for i in 0..2 {
+            vec.push(i.to_string());
+        }
+        vec.insert(1, String::from("a"));
+        vec.get_mut::<String>(0).push('!');
+        assert_eq!(vec.as_slice::<String>(), ["0!", "a", "1"]);
+        assert_eq!(vec.remove::<String>(1), "a");
+        assert_eq!(vec.swap_remove::<String>(0), "0!");
+        assert_eq!(vec.pop::<String>().unwrap(), "1");
+        assert!(vec.is_empty());
+        assert!(!vec.spilled());
+
+        vec.push(String::from("b"));
+        let vec = vec.into_vec();
+        assert_eq!(vec.as_slice::<String>(), ["b"]);
+    }
+
+    #[test]
+    fn drop_elements() {
+        let rc = Rc::new(());
+        let mut vec = UntypedSmallVec::<16>::new::<Rc<()>>();
+        vec.push(rc.clone());
+        vec.push(rc.clone());
+        vec.swap_remove_drop(0);
+        assert_eq!(Rc::strong_count(&rc), 2);
+        drop(vec);
+        assert_eq!(Rc::strong_count(&rc), 1);
+
+        let mut vec = UntypedSmallVec::<16>::new::<Rc<()>>();
+        for _ in 0..10 {
+            vec.push(rc.clone());
+        }
+        vec.truncate(5);
+        assert_eq!(Rc::strong_count(&rc), 6);
+        drop(vec);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn inline_drops() {
+        let rc = Rc::new(());
+        let mut vec = UntypedSmallVec::<64>::new::<Rc<()>>();
+        for _ in 0..8 {
+            vec.push(rc.clone());
+        }
+        assert!(!vec.spilled());
+
+        vec.truncate(6);
+        assert_eq!(Rc::strong_count(&rc), 7);
+        let mut keep = false;
+        vec.retain::<Rc<()>, _>(|_| {
+            keep = !keep;
+            keep
+        });
+        assert_eq!(Rc::strong_count(&rc), 4);
+        vec.dedup_by::<Rc<()>, _>(|a, b| Rc::ptr_eq(a, b));
+        assert_eq!(Rc::strong_count(&rc), 2);
+        vec.push(rc.clone());
+        drop(vec.drain::<Rc<()>, _>(..1));
+        assert_eq!(Rc::strong_count(&rc), 2);
+        assert!(!vec.spilled());
+        drop(vec);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn retain_dedup_drain() {
+        for count in [4u32, 20] {
+            let mut vec = UntypedSmallVec::<16>::new::<u32>();
+            for i in 0..count {
+                vec.push(i / 2);
+            }
+            vec.dedup_by::<u32, _>(|a, b| a == b);
+            assert_eq!(vec.len(), count as usize / 2);
+            vec.retain::<u32, _>(|&i| i % 2 == 0);
+            assert!(vec.iter::<u32>().all(|i| i % 2 == 0));
+
+            let drained: Vec<u32> = vec.drain::<u32, _>(..1).collect();
+            assert_eq!(drained, [0]);
+            assert_eq!(vec.len(), count as usize / 4 - 1);
+        }
+    }
+
+    #[test]
+    fn into_iter() {
+        let mut vec = UntypedSmallVec::<96>::new::<String>();
+        for i in 0..3 {
+            vec.push(i.to_string());
+        }
+        let mut iter = vec.into_iter::<String>();
+        assert_eq!(iter.next().unwrap(), "0");
+        // Moving the iterator moves the inline elements along with it
+        let mut iter = Box::new(iter);
+        assert_eq!(iter.next_back().unwrap(), "2");
+        assert_eq!(iter.as_slice(), ["1"]);
+
+        let rc = Rc::new(());
+        let mut vec = UntypedSmallVec::<16>::new::<Rc<()>>();
+        for _ in 0..5 {
+            vec.push(rc.clone());
+        }
+        assert!(vec.spilled());
+        let mut iter = vec.into_iter::<Rc<()>>();
+        iter.next();
+        drop(iter);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn reserve_and_shrink() {
+        let mut vec = UntypedSmallVec::<16>::with_capacity::<u32>(4);
+        assert!(!vec.spilled());
+        vec.reserve_exact(5);
+        assert!(vec.spilled());
+        assert_eq!(vec.capacity(), 5);
+
+        for i in 0..5u32 {
+            vec.push(i);
+        }
+        vec.shrink_to_fit();
+        assert!(vec.spilled());
+        vec.truncate(2);
+        vec.shrink_to_fit();
+        assert!(!vec.spilled());
+        assert_eq!(vec.as_slice::<u32>(), [0, 1]);
+        assert_eq!(vec.capacity(), 4);
+    }
+
+    #[test]
+    fn shrink_to() {
+        let mut vec = UntypedSmallVec::<16>::with_capacity::<u32>(10);
+        assert_eq!(vec.capacity(), 10);
+        for i in 0..3u32 {
+            vec.push(i);
+        }
+        vec.shrink_to(6);
+        assert_eq!(vec.capacity(), 6);
+        vec.shrink_to(8);
+        assert_eq!(vec.capacity(), 6);
+        vec.shrink_to(2);
+        assert!(!vec.spilled());
+        assert_eq!(vec.as_slice::<u32>(), [0, 1, 2]);
+    }
+
+    fn ordered() -> ElementDescriptor {
+        ElementDescriptor::of::<String>()
+            .with_clone::<String>()
+            .with_eq::<String>()
+            .with_hash::<String>()
+            .with_ord::<String>()
+    }
+
+    fn hash_of(value: &impl Hash) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn clone_eq_hash() {
+        let mut vec = UntypedSmallVec::<64>::from_descriptor(ordered());
+        for i in 0..2 {
+            vec.push(i.to_string());
+        }
+        let mut spilled = UntypedSmallVec::<0>::from_descriptor(ordered());
+        spilled.push(String::from("0"));
+        spilled.push(String::from("1"));
+
+        let clone = vec.clone();
+        assert!(!clone.spilled());
+        assert_eq!(clone.as_slice::<String>(), ["0", "1"]);
+        assert!(vec == clone && vec == spilled);
+        assert_eq!(hash_of(&vec), hash_of(&spilled));
+
+        spilled.push(String::from("2"));
+        assert!(vec != spilled);
+        assert!(UntypedSmallVec::<16>::new::<u32>().try_clone().is_none());
+        let copies = UntypedSmallVec::<16>::new_cloneable::<u32>();
+        assert!(copies.try_clone().is_some());
+    }
+
+    #[test]
+    fn sort_and_search() {
+        let mut vec = UntypedSmallVec::<128>::from_descriptor(ordered());
+        for s in ["d", "b", "c", "a"] {
+            vec.push(String::from(s));
+        }
+        vec.sort();
+        assert_eq!(vec.as_slice::<String>(), ["a", "b", "c", "d"]);
+
+        let needle = String::from("c");
+        let missing = String::from("bb");
+        unsafe {
+            assert!(vec.contains_raw(to_ptr(&needle)));
+            assert_eq!(vec.position_raw(to_ptr(&needle)), Some(2));
+            assert_eq!(vec.binary_search_raw(to_ptr(&needle)), Ok(2));
+            assert_eq!(vec.binary_search_raw(to_ptr(&missing)), Err(2));
+        }
+
+        let permutation =
+            vec.sort_by_key_raw(|ptr| std::cmp::Reverse(unsafe { &*ptr.cast::<String>() }.clone()));
+        assert_eq!(permutation, [3, 2, 1, 0]);
+        vec.apply_permutation(&permutation);
+        assert_eq!(vec.as_slice::<String>(), ["d", "c", "b", "a"]);
+        vec.sort_unstable();
+        assert_eq!(vec.as_slice::<String>(), ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn gather_and_scatter() {
+        let rc = Rc::new(());
+        let mut vec = UntypedSmallVec::<32>::new_cloneable::<Rc<()>>();
+        for _ in 0..3 {
+            vec.push(rc.clone());
+        }
+
+        let gathered = vec.gather(&[2, 0, 2]);
+        assert_eq!(gathered.len(), 3);
+        assert_eq!(Rc::strong_count(&rc), 7);
+
+        let other = Rc::new(());
+        let mut src = UntypedSmallVec::<8>::new_cloneable::<Rc<()>>();
+        src.push(other.clone());
+        vec.scatter(&[1], src);
+        assert_eq!(Rc::strong_count(&rc), 6);
+        assert!(Rc::ptr_eq(vec.get::<Rc<()>>(1), &other));
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds or repeated")]
+    fn repeated_permutation() {
+        let mut vec = UntypedSmallVec::<16>::new::<u8>();
+        vec.push(0u8);
+        vec.push(1u8);
+        vec.apply_permutation(&[1, 1]);
+    }
+
+    #[test]
+    fn stores_one_descriptor() {
+        // The inline buffer doubles as the heap pointer once spilled
+        let overhead =
+            std::mem::size_of::<UntypedSmallVec<16>>() - std::mem::size_of::<ElementDescriptor>();
+        assert!(overhead <= 48, "overhead is {} bytes", overhead);
+        assert_eq!(
+            std::mem::size_of::<UntypedSmallVec<64>>(),
+            std::mem::size_of::<UntypedSmallVec<16>>() + 48
+        );
+    }
+
+    #[test]
+    fn over_aligned_elements_spill() {
+        #[derive(Debug, PartialEq)]
+        #[repr(align(32))]
+        struct Aligned(u8);
+
+        let mut vec = UntypedSmallVec::<128>::new::<Aligned>();
+        assert_eq!(vec.inline_capacity(), 0);
+        vec.push(Aligned(1));
+        assert!(vec.spilled());
+        assert_eq!(vec.get::<Aligned>(0) as *const Aligned as usize % 32, 0);
+    }
+
+    #[test]
+    fn zsts_never_spill() {
+        let mut vec = UntypedSmallVec::<0>::new::<()>();
+        for _ in 0..100 {
+            vec.push(());
+        }
+        assert_eq!(vec.len(), 100);
+        assert!(!vec.spilled());
+    }
+
+    #[test]
+    fn raw_elements() {
+        let desc = unsafe {
+            ElementDescriptor::new("u32 pair", std::alloc::Layout::new::<[u32; 2]>(), None)
+        };
+        let mut vec = UntypedSmallVec::<16>::from_descriptor(desc);
+        for i in 0..3u32 {
+            let value = ManuallyDrop::new([i, i * 10]);
+            unsafe { vec.push_raw(value.as_ptr().cast::<u8>()) };
+        }
+        assert!(vec.spilled());
+
+        let mut out = [0u32; 2];
+        unsafe {
+            vec.swap_remove_raw(0, out.as_mut_ptr().cast::<u8>());
+            assert_eq!(out, [0, 0]);
+            assert_eq!(*vec.get_raw(0).cast::<[u32; 2]>(), [2, 20]);
+            assert!(vec.pop_raw(out.as_mut_ptr().cast::<u8>()));
+        }
+        assert_eq!(out, [1, 10]);
+    }
+
+    #[test]
+    fn debug() {
+        let mut vec = UntypedSmallVec::<16>::new_debug::<u8>();
+        vec.push(1u8);
+        vec.push(2u8);
+        assert_eq!(format!("{:?}", vec), "[1, 2]");
+    }
+
+    #[test]
+    #[should_panic(expected = "Type mismatch")]
+    fn mismatched_push() {
+        let mut vec = UntypedSmallVec::<16>::new::<u32>();
+        vec.push(0i32);
+    }
+}
@@ -0,0 +1,261 @@
+use crate::{ElementDescriptor, UntypedVec};
+
+/// Maps integer ids to elements, with O(1) insertion, removal and lookup
+///
+/// Elements are kept densely packed in an [`UntypedVec`], next to a parallel array of their ids.
+/// A sparse array indexed by id points into the dense arrays
+pub struct SparseSet {
+    sparse: Vec<Option<usize>>,
+    dense: UntypedVec,
+    ids: Vec<usize>,
+}
+
+impl SparseSet {
+    pub fn new<T: 'static>() -> Self {
+        Self::from_descriptor(ElementDescriptor::of::<T>())
+    }
+
+    /// Creates an empty set for elements described by `desc`
+    pub fn from_descriptor(desc: ElementDescriptor) -> Self {
+        Self {
+            sparse: Vec::new(),
+            dense: UntypedVec::from_descriptor(desc),
+            ids: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn contains(&self, id: usize) -> bool {
+        self.dense_index(id).is_some()
+    }
+
+    /// Inserts `value` for `id`, dropping the previous value if there was one
+    #[track_caller]
+    pub fn insert<T: 'static>(&mut self, id: usize, value: T) {
+        match self.dense_index(id) {
+            Some(index) => *self.dense.get_mut(index) = value,
+            None => {
+                self.reserve_id(id);
+                self.dense.push(value);
+                self.link(id);
+            }
+        }
+    }
+
+    /// Moves the element behind `src` into the set for `id`, dropping the previous value if there was one
+    ///
+    /// # Safety
+    /// See [`UntypedVec::push_raw`]
+    #[track_caller]
+    pub unsafe fn insert_raw(&mut self, id: usize, src: *const u8) {
+        self.remove_drop(id);
+        self.reserve_id(id);
+        self.dense.push_raw(src);
+        self.link(id);
+    }
+
+    /// Removes and returns the value for `id`
+    #[track_caller]
+    pub fn remove<T: 'static>(&mut self, id: usize) -> Option<T> {
+        // Removing from the dense array first checks the type before anything is changed
+        let value = self.dense.swap_remove(self.dense_index(id)?);
+        self.unlink(id);
+        Some(value)
+    }
+
+    /// Removes the value for `id` and drops it, returning whether there was one
+    pub fn remove_drop(&mut self, id: usize) -> bool {
+        match self.unlink(id) {
+            Some(index) => {
+                self.dense.swap_remove_drop(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    #[track_caller]
+    pub fn get<T: 'static>(&self, id: usize) -> Option<&T> {
+        self.dense_index(id).map(|index| self.dense.get(index))
+    }
+    #[track_caller]
+    pub fn get_mut<T: 'static>(&mut self, id: usize) -> Option<&mut T> {
+        self.dense_index(id).map(|index| self.dense.get_mut(index))
+    }
+
+    /// Returns a pointer to the value for `id`, see [`UntypedVec::get_raw`]
+    pub fn get_raw(&self, id: usize) -> Option<*const u8> {
+        self.dense_index(id).map(|index| self.dense.get_raw(index))
+    }
+    /// Returns a mutable pointer to the value for `id`, see [`UntypedVec::get_raw_mut`]
+    pub fn get_raw_mut(&mut self, id: usize) -> Option<*mut u8> {
+        self.dense_index(id)
+            .map(|index| self.dense.get_raw_mut(index))
+    }
+
+    /// Returns the ids of all elements, in dense order
+    pub fn ids(&self) -> &[usize] {
+        &self.ids
+    }
+    /// Returns the densely packed elements, in the same order as [`SparseSet::ids`]
+    pub fn dense(&self) -> &UntypedVec {
+        &self.dense
+    }
+
+    /// Returns an iterator over the ids and values of all elements, in dense order
+    #[track_caller]
+    pub fn iter<T: 'static>(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.ids.iter().copied().zip(self.dense.iter::<T>())
+    }
+    /// Returns an iterator over the ids and mutable values of all elements, in dense order
+    #[track_caller]
+    pub fn iter_mut<T: 'static>(&mut self) -> impl Iterator<Item = (usize, &mut T)> {
+        self.ids.iter().copied().zip(self.dense.iter_mut::<T>())
+    }
+
+    pub fn clear(&mut self) {
+        self.sparse.clear();
+        self.ids.clear();
+        self.dense.clear();
+    }
+
+    fn dense_index(&self, id: usize) -> Option<usize> {
+        self.sparse.get(id).copied().flatten()
+    }
+
+    /// Makes room for `id` in the sparse and id arrays, so linking it can't fail.
+    /// Must be called before pushing onto the dense array, which is then left untouched on a panic
+    #[track_caller]
+    fn reserve_id(&mut self, id: usize) {
+        let sparse_len = id
+            .checked_add(1)
+            .unwrap_or_else(|| panic!("Id {} is too large for a sparse set", id));
+        if sparse_len > self.sparse.len() {
+            self.sparse.resize(sparse_len, None);
+        }
+        self.ids.reserve(1);
+    }
+
+    /// Points `id` at the element that was just pushed onto the dense array.
+    /// Room for `id` must have been made with [`SparseSet::reserve_id`]
+    fn link(&mut self, id: usize) {
+        self.sparse[id] = Some(self.ids.len());
+        self.ids.push(id);
+    }
+
+    /// Forgets `id`, mirroring the swap remove of its element in the dense array.
+    /// Returns the dense index the element must be removed from
+    fn unlink(&mut self, id: usize) -> Option<usize> {
+        let index = self.sparse.get_mut(id)?.take()?;
+        self.ids.swap_remove(index);
+        if let Some(&moved) = self.ids.get(index) {
+            self.sparse[moved] = Some(index);
+        }
+        Some(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::ManuallyDrop;
+
+    use crate::{ElementDescriptor, SparseSet};
+
+    #[test]
+    fn insert_remove() {
+        let mut set = SparseSet::new::<String>();
+        for id in [3, 10, 0, 7] {
+            set.insert(id, id.to_string());
+        }
+
+        assert_eq!(set.len(), 4);
+        assert_eq!(set.ids(), [3, 10, 0, 7]);
+        assert_eq!(set.get::<String>(10).unwrap(), "10");
+        assert!(set.get::<String>(5).is_none());
+        assert!(set.get::<String>(100).is_none());
+
+        assert_eq!(set.remove::<String>(3).unwrap(), "3");
+        assert!(!set.contains(3));
+        assert_eq!(set.ids(), [7, 10, 0]);
+        assert_eq!(set.get::<String>(7).unwrap(), "7");
+        assert!(set.remove::<String>(3).is_none());
+
+        set.insert(0, String::from("zero"));
+        assert_eq!(set.len(), 3);
+        assert_eq!(set.get::<String>(0).unwrap(), "zero");
+
+        assert!(set.remove_drop(0));
+        assert!(!set.remove_drop(0));
+        assert_eq!(set.len(), 2);
+
+        set.get_mut::<String>(10).unwrap().push('!');
+        let items: Vec<_> = set.iter::<String>().collect();
+        assert_eq!(items, [(7, &String::from("7")), (10, &String::from("10!"))]);
+    }
+
+    #[test]
+    fn remove_mismatched_type() {
+        let mut set = SparseSet::new::<u32>();
+        set.insert(0, 0u32);
+        set.insert(1, 1u32);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            set.remove::<i32>(0);
+        }));
+        assert!(result.is_err());
+        assert_eq!(set.get::<u32>(0), Some(&0));
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn insert_huge_id() {
+        let mut set = SparseSet::new::<String>();
+        set.insert(0, String::from("0"));
+
+        for id in [usize::MAX, usize::MAX / 2] {
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                set.insert(id, id.to_string());
+            }));
+            assert!(result.is_err());
+            assert_eq!(set.len(), 1);
+            assert_eq!(set.dense().len(), 1);
+        }
+        assert_eq!(set.get::<String>(0).unwrap(), "0");
+    }
+
+    #[test]
+    fn remove_last() {
+        let mut set = SparseSet::new::<usize>();
+        set.insert(1, 1usize);
+        set.insert(2, 2usize);
+        assert_eq!(set.remove::<usize>(2), Some(2));
+        assert_eq!(set.get::<usize>(1), Some(&1));
+        assert_eq!(set.remove::<usize>(1), Some(1));
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn runtime_descriptor() {
+        let desc = unsafe {
+            ElementDescriptor::new("u64 pair", std::alloc::Layout::new::<[u64; 2]>(), None)
+        };
+
+        let mut set = SparseSet::from_descriptor(desc);
+        for id in 0..10u64 {
+            let value = ManuallyDrop::new([id, id * id]);
+            unsafe { set.insert_raw(id as usize, value.as_ptr().cast::<u8>()) };
+        }
+        set.remove_drop(4);
+
+        let value = unsafe { *set.get_raw(9).unwrap().cast::<[u64; 2]>() };
+        assert_eq!(value, [9, 81]);
+        assert!(set.get_raw(4).is_none());
+        assert_eq!(set.dense().len(), 9);
+    }
+}
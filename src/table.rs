@@ -0,0 +1,373 @@
+use std::{any::TypeId, collections::HashMap};
+
+use crate::{ElementDescriptor, UntypedVec};
+
+/// A struct-of-arrays table: one [`UntypedVec`] column per component type, all of the same length
+#[derive(Default)]
+pub struct Table {
+    columns: HashMap<TypeId, UntypedVec>,
+    len: usize,
+}
+
+impl Table {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a column for elements of type `T`. Does nothing if the column already exists
+    ///
+    /// # Panics
+    /// Panics if the table isn't empty
+    #[track_caller]
+    pub fn add_column<T: 'static>(&mut self) {
+        self.add_column_from_descriptor(ElementDescriptor::of::<T>())
+    }
+
+    /// Adds a column for elements described by `desc`. Does nothing if the column already exists
+    ///
+    /// # Panics
+    /// Panics if the table isn't empty, or `desc` has no [`TypeId`]
+    #[track_caller]
+    pub fn add_column_from_descriptor(&mut self, desc: ElementDescriptor) {
+        assert!(
+            self.is_empty(),
+            "Columns can only be added to an empty table"
+        );
+        let type_id = desc.type_id().unwrap_or_else(|| {
+            panic!(
+                "Columns are keyed by TypeId, but `{}` has none",
+                desc.type_name()
+            )
+        });
+
+        self.columns
+            .entry(type_id)
+            .or_insert_with(|| UntypedVec::from_descriptor(desc));
+    }
+
+    /// Returns the number of rows
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn has_column<T: 'static>(&self) -> bool {
+        self.columns.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Appends a row, given as a tuple holding one value for every column
+    ///
+    /// # Panics
+    /// Panics if the types in the row don't match the columns one to one
+    #[track_caller]
+    pub fn push_row<R: Row>(&mut self, row: R) {
+        let type_ids = R::type_ids();
+        let type_ids = type_ids.as_ref();
+        // Rows hold at most 8 values, so the duplicate scan is cheap and doesn't allocate
+        let matches_columns = type_ids.len() == self.columns.len()
+            && type_ids
+                .iter()
+                .enumerate()
+                .all(|(i, id)| self.columns.contains_key(id) && !type_ids[..i].contains(id));
+        assert!(
+            matches_columns,
+            "Row `{}` doesn't match the columns of the table",
+            std::any::type_name::<R>()
+        );
+
+        // Reserve up front, so the row is never pushed halfway
+        for column in self.columns.values_mut() {
+            column.reserve(1);
+        }
+        row.push_into(self);
+        self.len += 1;
+    }
+
+    /// Removes the row at `row`, replacing it with the last row, and drops its values
+    ///
+    /// # Panics
+    /// Panics if `row` is out of bounds
+    #[track_caller]
+    pub fn swap_remove_row(&mut self, row: usize) {
+        assert!(
+            row < self.len,
+            "Row index out of bounds: the len is {} but the row is {}",
+            self.len,
+            row
+        );
+
+        self.len -= 1;
+        self.for_each_column(|column| column.swap_remove_drop(row));
+    }
+
+    /// Removes all rows
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.for_each_column(UntypedVec::clear);
+    }
+
+    /// # Panics
+    /// Panics if there's no column of type `T`, or `row` is out of bounds
+    #[track_caller]
+    pub fn get<T: 'static>(&self, row: usize) -> &T {
+        self.column_vec::<T>().get(row)
+    }
+    /// # Panics
+    /// Panics if there's no column of type `T`, or `row` is out of bounds
+    #[track_caller]
+    pub fn get_mut<T: 'static>(&mut self, row: usize) -> &mut T {
+        self.column_vec_mut::<T>().get_mut(row)
+    }
+
+    /// Returns the column of type `T` as a slice
+    ///
+    /// # Panics
+    /// Panics if there's no column of type `T`
+    #[track_caller]
+    pub fn column<T: 'static>(&self) -> &[T] {
+        self.column_vec::<T>().as_slice()
+    }
+    /// Returns the column of type `T` as a mutable slice
+    ///
+    /// # Panics
+    /// Panics if there's no column of type `T`
+    #[track_caller]
+    pub fn column_mut<T: 'static>(&mut self) -> &mut [T] {
+        self.column_vec_mut::<T>().as_mut_slice()
+    }
+
+    /// Returns the column with the given [`TypeId`], for callers that don't know its type
+    pub fn column_raw(&self, type_id: TypeId) -> Option<&UntypedVec> {
+        self.columns.get(&type_id)
+    }
+
+    /// Returns an iterator over all columns, in no particular order
+    pub fn columns(&self) -> impl Iterator<Item = &UntypedVec> {
+        self.columns.values()
+    }
+
+    /// Calls `f` on every column. If `f` panics, the remaining columns are still visited while
+    /// unwinding, so a panicking destructor can't leave the columns with different lengths
+    fn for_each_column(&mut self, f: impl FnMut(&mut UntypedVec)) {
+        struct Guard<'a, I: Iterator<Item = &'a mut UntypedVec>, F: FnMut(&mut UntypedVec)> {
+            columns: I,
+            f: F,
+        }
+        impl<'a, I: Iterator<Item = &'a mut UntypedVec>, F: FnMut(&mut UntypedVec)> Drop
+            for Guard<'a, I, F>
+        {
+            fn drop(&mut self) {
+                self.columns.by_ref().for_each(&mut self.f);
+            }
+        }
+
+        let mut guard = Guard {
+            columns: self.columns.values_mut(),
+            f,
+        };
+        for column in guard.columns.by_ref() {
+            (guard.f)(column);
+        }
+    }
+
+    #[track_caller]
+    fn column_vec<T: 'static>(&self) -> &UntypedVec {
+        self.columns
+            .get(&TypeId::of::<T>())
+            .unwrap_or_else(|| panic!("No column of `{}`", std::any::type_name::<T>()))
+    }
+    #[track_caller]
+    fn column_vec_mut<T: 'static>(&mut self) -> &mut UntypedVec {
+        self.columns
+            .get_mut(&TypeId::of::<T>())
+            .unwrap_or_else(|| panic!("No column of `{}`", std::any::type_name::<T>()))
+    }
+}
+
+/// A tuple of values that can be pushed into a [`Table`] as a single row.
+/// Implemented for tuples of up to 8 values, and sealed so the table can trust its rows
+pub trait Row: sealed::Row {}
+
+mod sealed {
+    use std::any::TypeId;
+
+    use crate::Table;
+
+    pub trait Row {
+        type TypeIds: AsRef<[TypeId]>;
+
+        /// The [`TypeId`] of every value in the row, in order
+        fn type_ids() -> Self::TypeIds;
+
+        /// Pushes every value onto its column. The table has checked that the columns match
+        /// and has reserved space for the row
+        fn push_into(self, table: &mut Table);
+    }
+}
+
+macro_rules! impl_row {
+    ($len:literal; $($name:ident),+) => {
+        impl<$($name: 'static),+> Row for ($($name,)+) {}
+
+        impl<$($name: 'static),+> sealed::Row for ($($name,)+) {
+            type TypeIds = [TypeId; $len];
+
+            fn type_ids() -> Self::TypeIds {
+                [$(TypeId::of::<$name>()),+]
+            }
+
+            #[allow(non_snake_case)]
+            fn push_into(self, table: &mut Table) {
+                let ($($name,)+) = self;
+                $(table.column_vec_mut::<$name>().push($name);)+
+            }
+        }
+    };
+}
+
+impl_row!(1; A);
+impl_row!(2; A, B);
+impl_row!(3; A, B, C);
+impl_row!(4; A, B, C, D);
+impl_row!(5; A, B, C, D, E);
+impl_row!(6; A, B, C, D, E, F);
+impl_row!(7; A, B, C, D, E, F, G);
+impl_row!(8; A, B, C, D, E, F, G, H);
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::Cell,
+        panic::{catch_unwind, AssertUnwindSafe},
+        rc::Rc,
+    };
+
+    use crate::Table;
+
+    #[derive(Debug, PartialEq)]
+    struct Position(f32, f32);
+    #[derive(Debug, PartialEq)]
+    struct Name(String);
+
+    fn table() -> Table {
+        let mut table = Table::new();
+        table.add_column::<Position>();
+        table.add_column::<Name>();
+        table
+    }
+
+    #[test]
+    fn push_rows() {
+        let mut table = table();
+        for i in 0..10 {
+            table.push_row((Position(i as f32, 0.0), Name(i.to_string())));
+        }
+        // Column order doesn't matter
+        table.push_row((Name(String::from("last")), Position(-1.0, -1.0)));
+
+        assert_eq!(table.len(), 11);
+        assert_eq!(table.get::<Position>(3), &Position(3.0, 0.0));
+        assert_eq!(table.get::<Name>(10), &Name(String::from("last")));
+
+        table.get_mut::<Position>(0).1 = 5.0;
+        assert_eq!(table.column::<Position>()[0], Position(0.0, 5.0));
+        for position in table.column_mut::<Position>() {
+            position.1 += 1.0;
+        }
+        assert!(table.column::<Position>().iter().all(|p| p.1 >= 0.0));
+        assert_eq!(table.columns().count(), 2);
+    }
+
+    #[test]
+    fn swap_remove_rows() {
+        let count = Rc::new(Cell::new(0));
+        struct Counter(Rc<Cell<usize>>);
+        impl Drop for Counter {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let mut table = table();
+        table.add_column::<Counter>();
+        for i in 0..10 {
+            table.push_row((
+                Position(i as f32, 0.0),
+                Name(i.to_string()),
+                Counter(count.clone()),
+            ));
+        }
+
+        table.swap_remove_row(0);
+        assert_eq!(count.get(), 1);
+        assert_eq!(table.len(), 9);
+        assert_eq!(table.get::<Position>(0), &Position(9.0, 0.0));
+        assert_eq!(table.get::<Name>(0), &Name(String::from("9")));
+
+        table.clear();
+        assert_eq!(count.get(), 10);
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn panicking_destructor() {
+        struct Bomb;
+        impl Drop for Bomb {
+            fn drop(&mut self) {
+                if !std::thread::panicking() {
+                    panic!("boom");
+                }
+            }
+        }
+
+        let mut table = table();
+        table.add_column::<Bomb>();
+        table.add_column::<u8>();
+        table.add_column::<u16>();
+        for i in 0..4 {
+            table.push_row((
+                Position(i as f32, 0.0),
+                Name(i.to_string()),
+                Bomb,
+                i as u8,
+                i as u16,
+            ));
+        }
+        let consistent = |table: &Table| table.columns().all(|column| column.len() == table.len());
+
+        let result = catch_unwind(AssertUnwindSafe(|| table.swap_remove_row(0)));
+        assert!(result.is_err());
+        assert_eq!(table.len(), 3);
+        assert!(consistent(&table));
+        assert_eq!(table.get::<u16>(0), &3);
+
+        let result = catch_unwind(AssertUnwindSafe(|| table.clear()));
+        assert!(result.is_err());
+        assert!(table.is_empty());
+        assert!(consistent(&table));
+    }
+
+    #[test]
+    #[should_panic(expected = "doesn't match the columns of the table")]
+    fn push_incomplete_row() {
+        let mut table = table();
+        table.push_row((Position(0.0, 0.0),));
+    }
+
+    #[test]
+    #[should_panic(expected = "doesn't match the columns of the table")]
+    fn push_duplicate_row() {
+        let mut table = table();
+        table.push_row((Position(0.0, 0.0), Position(0.0, 0.0)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Columns can only be added to an empty table")]
+    fn add_column_to_non_empty_table() {
+        let mut table = table();
+        table.push_row((Position(0.0, 0.0), Name(String::new())));
+        table.add_column::<usize>();
+    }
+}
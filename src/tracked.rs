@@ -0,0 +1,201 @@
+use crate::{ElementDescriptor, UntypedVec};
+
+/// A point in time for change detection, see [`TrackedVec`]
+pub type Tick = u64;
+
+/// An [`UntypedVec`] that records, per element, the tick it was added at and the tick it was last
+/// mutably accessed at
+///
+/// The current tick is set by the owner, typically once per frame or system run, and elements
+/// added or changed after a given tick can then be queried with [`TrackedVec::added_since`]
+/// and [`TrackedVec::changed_since`]
+pub struct TrackedVec {
+    vec: UntypedVec,
+    added: Vec<Tick>,
+    changed: Vec<Tick>,
+    tick: Tick,
+}
+
+impl TrackedVec {
+    pub fn new<T: 'static>() -> Self {
+        Self::from_descriptor(ElementDescriptor::of::<T>())
+    }
+
+    /// Creates an empty vector for elements described by `desc`
+    pub fn from_descriptor(desc: ElementDescriptor) -> Self {
+        Self {
+            vec: UntypedVec::from_descriptor(desc),
+            added: Vec::new(),
+            changed: Vec::new(),
+            tick: 0,
+        }
+    }
+
+    /// Returns the tick that additions and changes are currently recorded at
+    pub fn tick(&self) -> Tick {
+        self.tick
+    }
+    pub fn set_tick(&mut self, tick: Tick) {
+        self.tick = tick;
+    }
+    /// Advances the current tick by one, returning the new tick
+    pub fn increment_tick(&mut self) -> Tick {
+        self.tick += 1;
+        self.tick
+    }
+
+    pub fn len(&self) -> usize {
+        self.vec.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.vec.is_empty()
+    }
+
+    /// Returns the underlying vector. Reading through it doesn't count as a change
+    pub fn as_untyped(&self) -> &UntypedVec {
+        &self.vec
+    }
+
+    /// Pushes `elem`, recording it as added and changed at the current tick
+    #[track_caller]
+    pub fn push<T: 'static>(&mut self, elem: T) {
+        self.vec.push(elem);
+        self.added.push(self.tick);
+        self.changed.push(self.tick);
+    }
+
+    /// Moves an element in through the raw API, recording it as added and changed at the current tick
+    ///
+    /// # Safety
+    /// See [`UntypedVec::push_raw`]
+    #[track_caller]
+    pub unsafe fn push_raw(&mut self, src: *const u8) {
+        self.vec.push_raw(src);
+        self.added.push(self.tick);
+        self.changed.push(self.tick);
+    }
+
+    #[track_caller]
+    pub fn pop<T: 'static>(&mut self) -> Option<T> {
+        let elem = self.vec.pop()?;
+        self.added.pop();
+        self.changed.pop();
+        Some(elem)
+    }
+
+    /// Removes the element at `index`, replacing it and its ticks with the last element's
+    #[track_caller]
+    pub fn swap_remove<T: 'static>(&mut self, index: usize) -> T {
+        let elem = self.vec.swap_remove(index);
+        self.added.swap_remove(index);
+        self.changed.swap_remove(index);
+        elem
+    }
+
+    /// Removes the element at `index` and drops it, replacing it and its ticks with the last element's
+    #[track_caller]
+    pub fn swap_remove_drop(&mut self, index: usize) {
+        self.vec.swap_remove_drop(index);
+        self.added.swap_remove(index);
+        self.changed.swap_remove(index);
+    }
+
+    pub fn clear(&mut self) {
+        self.vec.clear();
+        self.added.clear();
+        self.changed.clear();
+    }
+
+    /// Returns the element at `index` without marking it as changed
+    #[track_caller]
+    pub fn get<T: 'static>(&self, index: usize) -> &T {
+        self.vec.get(index)
+    }
+    /// Returns the element at `index`, marking it as changed at the current tick
+    #[track_caller]
+    pub fn get_mut<T: 'static>(&mut self, index: usize) -> &mut T {
+        let elem = self.vec.get_mut(index);
+        self.changed[index] = self.tick;
+        elem
+    }
+
+    #[track_caller]
+    pub fn iter<T: 'static>(&self) -> std::slice::Iter<'_, T> {
+        self.vec.iter()
+    }
+
+    /// Returns the tick the element at `index` was added at
+    #[track_caller]
+    pub fn added_tick(&self, index: usize) -> Tick {
+        self.added[index]
+    }
+    /// Returns the tick the element at `index` was last mutably accessed at
+    #[track_caller]
+    pub fn changed_tick(&self, index: usize) -> Tick {
+        self.changed[index]
+    }
+
+    /// Returns an iterator over the indices of elements added after `tick`
+    pub fn added_since(&self, tick: Tick) -> impl Iterator<Item = usize> + '_ {
+        Self::indices_since(&self.added, tick)
+    }
+    /// Returns an iterator over the indices of elements added or mutably accessed after `tick`
+    pub fn changed_since(&self, tick: Tick) -> impl Iterator<Item = usize> + '_ {
+        Self::indices_since(&self.changed, tick)
+    }
+
+    fn indices_since(ticks: &[Tick], tick: Tick) -> impl Iterator<Item = usize> + '_ {
+        ticks
+            .iter()
+            .enumerate()
+            .filter(move |&(_, &t)| t > tick)
+            .map(|(index, _)| index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::TrackedVec;
+
+    #[test]
+    fn ticks() {
+        let mut vec = TrackedVec::new::<String>();
+        for i in 0..5 {
+            vec.push(i.to_string());
+        }
+
+        let last_run = vec.tick();
+        vec.increment_tick();
+        vec.push(String::from("new"));
+        vec.get_mut::<String>(1).push('!');
+        assert_eq!(vec.get::<String>(2), "2");
+
+        assert_eq!(vec.added_since(last_run).collect::<Vec<_>>(), [5]);
+        assert_eq!(vec.changed_since(last_run).collect::<Vec<_>>(), [1, 5]);
+        assert_eq!(vec.added_tick(5), 1);
+        assert_eq!(vec.changed_tick(1), 1);
+        assert_eq!(vec.changed_tick(0), 0);
+
+        let last_run = vec.tick();
+        vec.increment_tick();
+        assert_eq!(vec.changed_since(last_run).count(), 0);
+    }
+
+    #[test]
+    fn swap_remove_moves_ticks() {
+        let mut vec = TrackedVec::new::<usize>();
+        vec.push(0usize);
+        vec.push(1usize);
+        vec.set_tick(10);
+        vec.push(2usize);
+
+        assert_eq!(vec.swap_remove::<usize>(0), 0);
+        assert_eq!(vec.get::<usize>(0), &2);
+        assert_eq!(vec.added_since(0).collect::<Vec<_>>(), [0]);
+
+        vec.swap_remove_drop(0);
+        assert_eq!(vec.changed_since(0).count(), 0);
+        assert_eq!(vec.pop::<usize>(), Some(1));
+        assert!(vec.is_empty());
+    }
+}
@@ -0,0 +1,148 @@
+use std::{any::TypeId, collections::HashMap};
+
+use crate::UntypedVec;
+
+/// A heterogeneous collection holding one [`UntypedVec`] per element type, keyed by [`TypeId`]
+#[derive(Default)]
+pub struct TypeMap {
+    columns: HashMap<TypeId, UntypedVec>,
+}
+
+impl TypeMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of columns
+    pub fn len(&self) -> usize {
+        self.columns.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    pub fn contains<T: 'static>(&self) -> bool {
+        self.columns.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Pushes `value` onto the column of `T`, creating the column if needed
+    pub fn push<T: 'static>(&mut self, value: T) {
+        self.get_or_insert_column::<T>().push(value)
+    }
+
+    /// Returns an iterator over the elements of type `T`, which is empty if there's no such column
+    pub fn iter<T: 'static>(&self) -> std::slice::Iter<'_, T> {
+        match self.column::<T>() {
+            Some(column) => column.iter(),
+            None => [].iter(),
+        }
+    }
+    /// Returns an iterator over mutable references to the elements of type `T`
+    pub fn iter_mut<T: 'static>(&mut self) -> std::slice::IterMut<'_, T> {
+        match self.column_mut::<T>() {
+            Some(column) => column.iter_mut(),
+            None => [].iter_mut(),
+        }
+    }
+
+    /// Returns the column of `T`, creating an empty one if needed
+    pub fn get_or_insert_column<T: 'static>(&mut self) -> &mut UntypedVec {
+        self.columns
+            .entry(TypeId::of::<T>())
+            .or_insert_with(UntypedVec::new::<T>)
+    }
+
+    pub fn column<T: 'static>(&self) -> Option<&UntypedVec> {
+        self.columns.get(&TypeId::of::<T>())
+    }
+    pub fn column_mut<T: 'static>(&mut self) -> Option<&mut UntypedVec> {
+        self.columns.get_mut(&TypeId::of::<T>())
+    }
+
+    /// Returns the column with the given [`TypeId`], for callers that don't know its type
+    pub fn column_raw(&self, type_id: TypeId) -> Option<&UntypedVec> {
+        self.columns.get(&type_id)
+    }
+
+    /// Removes the column of `T`, handing it back. Dropping it drops its elements
+    pub fn remove_column<T: 'static>(&mut self) -> Option<UntypedVec> {
+        self.remove_column_raw(TypeId::of::<T>())
+    }
+    /// Removes the column with the given [`TypeId`], handing it back. Dropping it drops its elements
+    pub fn remove_column_raw(&mut self, type_id: TypeId) -> Option<UntypedVec> {
+        self.columns.remove(&type_id)
+    }
+
+    /// Returns an iterator over all columns, in no particular order
+    pub fn columns(&self) -> impl Iterator<Item = (TypeId, &UntypedVec)> {
+        self.columns
+            .iter()
+            .map(|(&type_id, column)| (type_id, column))
+    }
+
+    /// Removes all columns, dropping their elements
+    pub fn clear(&mut self) {
+        self.columns.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{any::TypeId, rc::Rc};
+
+    use crate::TypeMap;
+
+    #[test]
+    fn push_iter() {
+        let mut map = TypeMap::new();
+        for i in 0..10 {
+            map.push(i as u32);
+            map.push(i.to_string());
+        }
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.iter::<u32>().sum::<u32>(), 45);
+        assert_eq!(map.iter::<String>().nth(3).unwrap(), "3");
+        assert_eq!(map.iter::<u64>().count(), 0);
+        assert!(!map.contains::<u64>());
+
+        for value in map.iter_mut::<u32>() {
+            *value *= 2;
+        }
+        assert_eq!(map.column::<u32>().unwrap().get::<u32>(9), &18);
+        assert!(map.columns().any(|(id, _)| id == TypeId::of::<String>()));
+    }
+
+    #[test]
+    fn get_or_insert_column() {
+        let mut map = TypeMap::new();
+        let column = map.get_or_insert_column::<String>();
+        assert!(column.is_empty());
+        column.push(String::from("foo"));
+
+        assert_eq!(map.get_or_insert_column::<String>().len(), 1);
+        assert!(map.contains::<String>());
+    }
+
+    #[test]
+    fn remove_columns() {
+        let rc = Rc::new(());
+        let mut map = TypeMap::new();
+        for _ in 0..10 {
+            map.push(rc.clone());
+        }
+        map.push(0usize);
+        assert_eq!(Rc::strong_count(&rc), 11);
+
+        let column = map.remove_column::<Rc<()>>().unwrap();
+        assert_eq!(column.len(), 10);
+        assert!(!map.contains::<Rc<()>>());
+        drop(column);
+        assert_eq!(Rc::strong_count(&rc), 1);
+
+        map.push(rc.clone());
+        map.clear();
+        assert!(map.is_empty());
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+}
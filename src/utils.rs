@@ -1,21 +1,481 @@
-use std::alloc::Layout;
+use std::{
+    alloc::{handle_alloc_error, Layout},
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+    ops::{Bound, Range, RangeBounds},
+    ptr::NonNull,
+};
 
-pub(super) fn array_layout(layout: &Layout, amount: usize) -> Option<Layout> {
+use crate::{DebugFn, ElementDescriptor, TryReserveError, UntypedVec, UntypedVecError};
+
+/// Turns a failed reservation into a panic or an allocation error, like the standard [`Vec`] does
+#[track_caller]
+pub(super) fn handle_reserve(result: Result<(), TryReserveError>) {
+    match result {
+        Ok(()) => {}
+        Err(TryReserveError::CapacityOverflow) => panic!("{}", TryReserveError::CapacityOverflow),
+        Err(TryReserveError::AllocError { layout }) => handle_alloc_error(layout),
+    }
+}
+
+/// The layout of a buffer holding `amount` elements of `layout`, aligned to at least `align`.
+/// The stride between elements is unaffected by `align`
+pub(super) fn array_layout(layout: &Layout, amount: usize, align: usize) -> Option<Layout> {
     let (array_layout, offset) = repeat_layout(layout, amount)?;
     debug_assert_eq!(layout.size(), offset);
-    Some(array_layout)
+    array_layout.align_to(align).ok()
+}
+
+pub(super) fn check_index(index: usize, len: usize) -> Result<(), UntypedVecError> {
+    if index < len {
+        Ok(())
+    } else {
+        Err(UntypedVecError::IndexOutOfBounds { index, len })
+    }
+}
+
+/// Shortens the elements starting at `base` to `new_len`, dropping the rest.
+/// Does nothing if `new_len` is greater than `*len`
+///
+/// # Safety
+/// `base` must point to `*len` initialized elements described by `desc`
+pub(super) unsafe fn truncate(
+    desc: &ElementDescriptor,
+    base: *mut u8,
+    len: &mut usize,
+    new_len: usize,
+) {
+    let old_len = *len;
+    if new_len >= old_len {
+        return;
+    }
+
+    // Shrink first, so a panicking destructor leaks the tail instead of double dropping it
+    *len = new_len;
+    if let Some(drop) = desc.drop {
+        for i in new_len..old_len {
+            drop(base.add(i * desc.layout.size()));
+        }
+    }
+}
+
+/// Retains only the elements for which `f` returns true, preserving their order
+///
+/// # Safety
+/// `base` must point to `*len` initialized elements of type `T`
+pub(super) unsafe fn retain_mut<T, F>(base: *mut T, len: &mut usize, mut f: F)
+where
+    F: FnMut(&mut T) -> bool,
+{
+    // Closes the gap left by removed elements, even if `f` or a destructor panics
+    struct Guard<'a, T> {
+        base: *mut T,
+        len: &'a mut usize,
+        processed: usize,
+        deleted: usize,
+        original_len: usize,
+    }
+    impl<T> Drop for Guard<'_, T> {
+        fn drop(&mut self) {
+            if self.deleted > 0 {
+                unsafe {
+                    std::ptr::copy(
+                        self.base.add(self.processed),
+                        self.base.add(self.processed - self.deleted),
+                        self.original_len - self.processed,
+                    );
+                }
+            }
+            *self.len = self.original_len - self.deleted;
+        }
+    }
+
+    let original_len = *len;
+    *len = 0;
+    let mut guard = Guard {
+        base,
+        len,
+        processed: 0,
+        deleted: 0,
+        original_len,
+    };
+
+    while guard.processed < original_len {
+        let cur = base.add(guard.processed);
+        if !f(&mut *cur) {
+            guard.processed += 1;
+            guard.deleted += 1;
+            std::ptr::drop_in_place(cur);
+            continue;
+        }
+        if guard.deleted > 0 {
+            std::ptr::copy_nonoverlapping(cur, base.add(guard.processed - guard.deleted), 1);
+        }
+        guard.processed += 1;
+    }
+}
+
+/// Removes all but the first of consecutive elements for which `same_bucket` returns true
+///
+/// # Safety
+/// `base` must point to `*len` initialized elements of type `T`
+pub(super) unsafe fn dedup_by<T, F>(base: *mut T, len: &mut usize, mut same_bucket: F)
+where
+    F: FnMut(&mut T, &mut T) -> bool,
+{
+    let original_len = *len;
+    if original_len <= 1 {
+        return;
+    }
+
+    // Moves the unprocessed tail over the gap if `same_bucket` or a destructor panics
+    struct FillGapOnDrop<'a, T> {
+        base: *mut T,
+        len: &'a mut usize,
+        original_len: usize,
+        read: usize,
+        write: usize,
+    }
+    impl<T> Drop for FillGapOnDrop<'_, T> {
+        fn drop(&mut self) {
+            let items_left = self.original_len - self.read;
+            unsafe {
+                std::ptr::copy(
+                    self.base.add(self.read),
+                    self.base.add(self.write),
+                    items_left,
+                );
+            }
+            *self.len = self.write + items_left;
+        }
+    }
+
+    let mut gap = FillGapOnDrop {
+        base,
+        len,
+        original_len,
+        read: 1,
+        write: 1,
+    };
+
+    while gap.read < original_len {
+        let read_ptr = base.add(gap.read);
+        let prev_ptr = base.add(gap.write - 1);
+        if same_bucket(&mut *read_ptr, &mut *prev_ptr) {
+            gap.read += 1;
+            std::ptr::drop_in_place(read_ptr);
+        } else {
+            std::ptr::copy(read_ptr, base.add(gap.write), 1);
+            gap.write += 1;
+            gap.read += 1;
+        }
+    }
+
+    *gap.len = gap.write;
+    std::mem::forget(gap);
+}
+
+/// Returns the index of the first element equal to the one behind `elem`
+///
+/// # Panics
+/// Panics if the descriptor has no eq function
+///
+/// # Safety
+/// `base` must point to `len` initialized elements described by `desc`, and `elem` to one more
+#[track_caller]
+pub(super) unsafe fn position(
+    desc: &ElementDescriptor,
+    base: *const u8,
+    len: usize,
+    elem: *const u8,
+) -> Option<usize> {
+    let eq = desc.expect_eq_fn();
+    let size = desc.layout.size();
+    (0..len).find(|&i| eq(base.add(i * size), elem))
+}
+
+/// Binary searches the sorted elements for the one behind `elem`, like [`slice::binary_search`]
+///
+/// # Panics
+/// Panics if the descriptor has no cmp function
+///
+/// # Safety
+/// As in [`position`]
+#[track_caller]
+pub(super) unsafe fn binary_search(
+    desc: &ElementDescriptor,
+    base: *const u8,
+    len: usize,
+    elem: *const u8,
+) -> Result<usize, usize> {
+    let cmp = desc.expect_cmp_fn();
+    let size = desc.layout.size();
+    let mut left = 0;
+    let mut right = len;
+    while left < right {
+        let mid = left + (right - left) / 2;
+        match cmp(base.add(mid * size), elem) {
+            Ordering::Less => left = mid + 1,
+            Ordering::Greater => right = mid,
+            Ordering::Equal => return Ok(mid),
+        }
+    }
+    Err(left)
+}
+
+/// Returns the permutation that sorts the elements with the descriptor's cmp function, to be
+/// applied with [`permute`]. Equal elements keep their order if `stable` is set
+///
+/// # Panics
+/// Panics if the descriptor has no cmp function
+///
+/// # Safety
+/// `base` must point to `len` initialized elements described by `desc`
+#[track_caller]
+pub(super) unsafe fn sort_permutation(
+    desc: &ElementDescriptor,
+    base: *const u8,
+    len: usize,
+    stable: bool,
+) -> Vec<usize> {
+    let cmp = desc.expect_cmp_fn();
+    let size = desc.layout.size();
+    let compare = |&a: &usize, &b: &usize| cmp(base.add(a * size), base.add(b * size));
+    let mut permutation: Vec<usize> = (0..len).collect();
+    if stable {
+        permutation.sort_by(compare);
+    } else {
+        permutation.sort_unstable_by(compare);
+    }
+    permutation
+}
+
+/// Returns the permutation that stably sorts the elements by the key `f` extracts from each one
+///
+/// # Safety
+/// `base` must point to `len` initialized elements described by `desc`
+pub(super) unsafe fn sort_by_key<K, F>(
+    desc: &ElementDescriptor,
+    base: *const u8,
+    len: usize,
+    mut f: F,
+) -> Vec<usize>
+where
+    K: Ord,
+    F: FnMut(*const u8) -> K,
+{
+    let size = desc.layout.size();
+    let mut permutation: Vec<usize> = (0..len).collect();
+    permutation.sort_by_cached_key(|&i| f(base.add(i * size)));
+    permutation
+}
+
+/// # Panics
+/// Panics if `permutation` is not a permutation of `0..len`
+#[track_caller]
+pub(super) fn check_permutation(permutation: &[usize], len: usize) {
+    assert_eq!(
+        permutation.len(),
+        len,
+        "Permutation length should match the len of the vector"
+    );
+    let mut seen = vec![false; len];
+    for &index in permutation {
+        assert!(
+            index < len && !std::mem::replace(&mut seen[index], true),
+            "Index {} is out of bounds or repeated in the permutation",
+            index
+        );
+    }
+}
+
+/// Reorders the elements so that the element at `permutation[i]` ends up at index `i`,
+/// following each cycle of the permutation with a single element of scratch space
+///
+/// # Safety
+/// `base` must point to initialized elements described by `desc`, and `permutation` must be a
+/// permutation of their indices
+pub(super) unsafe fn permute(desc: &ElementDescriptor, base: *mut u8, permutation: &[usize]) {
+    let size = desc.layout.size();
+    let mut scratch = vec![0u8; size];
+    let mut visited = vec![false; permutation.len()];
+    for start in 0..permutation.len() {
+        if visited[start] || permutation[start] == start {
+            continue;
+        }
+
+        std::ptr::copy_nonoverlapping(base.add(start * size), scratch.as_mut_ptr(), size);
+        let mut hole = start;
+        loop {
+            visited[hole] = true;
+            let next = permutation[hole];
+            if next == start {
+                std::ptr::copy_nonoverlapping(scratch.as_ptr(), base.add(hole * size), size);
+                break;
+            }
+            std::ptr::copy_nonoverlapping(base.add(next * size), base.add(hole * size), size);
+            hole = next;
+        }
+    }
+}
+
+/// Appends duplicates of the elements at `indices` to the elements at `dst`, by byte copy for
+/// [`Copy`] types and with the clone function otherwise
+///
+/// # Panics
+/// Panics if an index is out of bounds
+///
+/// # Safety
+/// `src` must point to `src_len` initialized elements described by `desc`, which must be
+/// cloneable. `dst` must point to `*dst_len` elements of the same type, with room for the duplicates
+#[track_caller]
+pub(super) unsafe fn clone_elements(
+    desc: &ElementDescriptor,
+    src: *const u8,
+    src_len: usize,
+    indices: impl Iterator<Item = usize>,
+    dst: *mut u8,
+    dst_len: &mut usize,
+) {
+    debug_assert!(desc.is_cloneable());
+
+    let size = desc.layout.size();
+    for index in indices {
+        check_index(index, src_len).unwrap_or_else(|err| panic!("{}", err));
+        let (from, to) = (src.add(index * size), dst.add(*dst_len * size));
+        match desc.clone {
+            Some(clone) if !desc.copy => clone(from, to),
+            _ => std::ptr::copy_nonoverlapping(from, to, size),
+        }
+        // Bump the len after every element, so a panicking clone only drops finished clones
+        *dst_len += 1;
+    }
+}
+
+/// # Panics
+/// Panics if `src_desc` describes a different type, `src_len` doesn't match the number of
+/// indices, or an index is out of bounds
+#[track_caller]
+pub(super) fn check_scatter(
+    desc: &ElementDescriptor,
+    len: usize,
+    indices: &[usize],
+    src_desc: &ElementDescriptor,
+    src_len: usize,
+) {
+    assert!(
+        desc.describes_same_type(src_desc),
+        "Type mismatch: vector stores `{}` but was given a vector of `{}`",
+        desc.type_name,
+        src_desc.type_name
+    );
+    assert_eq!(
+        indices.len(),
+        src_len,
+        "Number of indices should match the len of the source vector"
+    );
+    for &index in indices {
+        check_index(index, len).unwrap_or_else(|err| panic!("{}", err));
+    }
+}
+
+/// Moves the elements at `src` over the elements at `indices`, dropping the replaced elements
+///
+/// # Safety
+/// `base` must point to initialized elements described by `desc`, and the indices must be in
+/// bounds. `src` must point to `indices.len()` initialized elements of the same type, which are
+/// owned by this function from now on. If a destructor panics the remaining ones are leaked
+pub(super) unsafe fn scatter(
+    desc: &ElementDescriptor,
+    base: *mut u8,
+    indices: &[usize],
+    src: *const u8,
+) {
+    let size = desc.layout.size();
+    // Suitably aligned space to drop replaced elements from
+    let mut replaced = UntypedVec::from_descriptor(*desc);
+    replaced.reserve_exact(1);
+    for (i, &index) in indices.iter().enumerate() {
+        let dst = base.add(index * size);
+        std::ptr::copy_nonoverlapping(dst, replaced.ptr().as_ptr(), size);
+        replaced.len = 1;
+        std::ptr::copy_nonoverlapping(src.add(i * size), dst, size);
+        replaced.clear();
+    }
+}
+
+/// Returns whether both runs of elements are pairwise equal
+///
+/// # Panics
+/// Panics if the descriptor has no eq function
+///
+/// # Safety
+/// `a` and `b` must point to `a_len` and `b_len` initialized elements described by `desc`
+#[track_caller]
+pub(super) unsafe fn elements_eq(
+    desc: &ElementDescriptor,
+    a: *const u8,
+    a_len: usize,
+    b: *const u8,
+    b_len: usize,
+) -> bool {
+    let eq = desc.expect_eq_fn();
+    let size = desc.layout.size();
+    a_len == b_len && (0..a_len).all(|i| eq(a.add(i * size), b.add(i * size)))
+}
+
+/// Feeds the len and every element into `state`
+///
+/// # Panics
+/// Panics if the descriptor has no hash function
+///
+/// # Safety
+/// `base` must point to `len` initialized elements described by `desc`
+#[track_caller]
+pub(super) unsafe fn hash_elements(
+    desc: &ElementDescriptor,
+    base: *const u8,
+    len: usize,
+    state: &mut dyn Hasher,
+) {
+    let hash = desc.expect_hash_fn();
+    state.write_usize(len);
+    for i in 0..len {
+        hash(base.add(i * desc.layout.size()), state);
+    }
+}
+
+/// Formats an element through its descriptor's debug function
+pub(super) struct DebugElement {
+    pub(super) ptr: *const u8,
+    pub(super) debug: DebugFn,
+}
+
+impl fmt::Debug for DebugElement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        unsafe { (self.debug)(self.ptr, f) }
+    }
+}
+
+/// Mirrors the heuristic of the standard [`Vec`]: tiny allocations are wasteful,
+/// so start small elements off with a few slots
+pub(super) const fn min_non_zero_capacity(layout: &Layout) -> usize {
+    if layout.size() == 1 {
+        8
+    } else if layout.size() <= 1024 {
+        4
+    } else {
+        1
+    }
 }
 
 pub(super) fn repeat_layout(layout: &Layout, n: usize) -> Option<(Layout, usize)> {
     let padded_size = layout.size() + padding_needed_for(layout, layout.align());
     let alloc_size = padded_size.checked_mul(n)?;
+    let layout = Layout::from_size_align(alloc_size, layout.align()).ok()?;
 
-    unsafe {
-        Some((
-            Layout::from_size_align_unchecked(alloc_size, layout.align()),
-            padded_size,
-        ))
-    }
+    Some((layout, padded_size))
 }
 
 pub(super) const fn padding_needed_for(layout: &Layout, align: usize) -> usize {
@@ -24,6 +484,12 @@ pub(super) const fn padding_needed_for(layout: &Layout, align: usize) -> usize {
     len_rounded_up.wrapping_sub(len)
 }
 
+/// A non-null pointer aligned to `align`, to stand in for an unallocated buffer
+pub(super) fn dangling(align: usize) -> NonNull<u8> {
+    // SAFETY: Alignments are never zero
+    unsafe { NonNull::new_unchecked(std::ptr::without_provenance_mut(align)) }
+}
+
 pub(super) const fn to_const_ptr<T>(val: &T) -> *const u8 {
     (val as *const T).cast::<u8>()
 }
@@ -33,6 +499,63 @@ pub(super) unsafe fn drop_ptr<T>(x: *mut u8) {
     x.cast::<T>().drop_in_place()
 }
 
-pub(super) fn convert_ptr<T>(ptr: *const u8) -> *const T {
-    ptr.cast::<T>()
+// SAFETY : x must point to valid data!
+pub(super) unsafe fn debug_ptr<T: fmt::Debug>(
+    x: *const u8,
+    f: &mut fmt::Formatter<'_>,
+) -> fmt::Result {
+    fmt::Debug::fmt(&*x.cast::<T>(), f)
+}
+
+// SAFETY : a and b must point to valid data!
+pub(super) unsafe fn eq_ptr<T: PartialEq>(a: *const u8, b: *const u8) -> bool {
+    *a.cast::<T>() == *b.cast::<T>()
+}
+
+// SAFETY : a and b must point to valid data!
+pub(super) unsafe fn cmp_ptr<T: Ord>(a: *const u8, b: *const u8) -> Ordering {
+    (*a.cast::<T>()).cmp(&*b.cast::<T>())
+}
+
+// SAFETY : x must point to valid data!
+pub(super) unsafe fn hash_ptr<T: Hash>(x: *const u8, mut state: &mut dyn Hasher) {
+    (*x.cast::<T>()).hash(&mut state)
+}
+
+// SAFETY : src must point to valid data, dst must be valid for a write of T!
+pub(super) unsafe fn clone_ptr<T: Clone>(src: *const u8, dst: *mut u8) {
+    dst.cast::<T>().write((*src.cast::<T>()).clone())
+}
+
+/// Resolves `range` against a slice of length `len`, panicking like slice indexing does
+#[track_caller]
+pub(super) fn slice_range<R: RangeBounds<usize>>(range: R, len: usize) -> Range<usize> {
+    let start = match range.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => start
+            .checked_add(1)
+            .unwrap_or_else(|| panic!("attempted to index slice from after maximum usize")),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&end) => end
+            .checked_add(1)
+            .unwrap_or_else(|| panic!("attempted to index slice up to maximum usize")),
+        Bound::Excluded(&end) => end,
+        Bound::Unbounded => len,
+    };
+
+    assert!(
+        start <= end,
+        "slice index starts at {} but ends at {}",
+        start,
+        end
+    );
+    assert!(
+        end <= len,
+        "range end index {} out of range for slice of length {}",
+        end,
+        len
+    );
+    start..end
 }